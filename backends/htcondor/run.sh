@@ -1,8 +1,6 @@
 #!/usr/bin/bash
 
-# apparently this vodoo kills all processes opened in this script
-# trap "trap - SIGTERM && kill -- -$$" SIGINT SIGTERM EXIT
-# ssh -N -L 8080:localhost:8080 lxplus7103 &
-# sleep 10
-
-/home/home4/institut_1b/jheuel/repositories/zoidberg/target/release/zoidberg_client
+# the server is reached directly over TLS, pass --ca-cert if it uses a
+# self-signed certificate; exec lets the client receive the SIGTERM of
+# HTCondor and shut down gracefully
+exec /home/home4/institut_1b/jheuel/repositories/zoidberg/target/release/zoidberg_client --threads 1 --memory 4096 "$@"
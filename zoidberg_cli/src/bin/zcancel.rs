@@ -0,0 +1,30 @@
+use clap::{arg, value_parser, App};
+use std::error::Error;
+
+use zoidberg_cli::{common_args, connect, print_jobs};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let matches = common_args(App::new("zcancel"))
+        .about("Cancels jobs on a Zoidberg server")
+        .arg(arg!(<IDS> ... "Jobs to cancel").value_parser(value_parser!(i32)))
+        .arg(arg!(-a --array "Cancel every task of the job arrays with these IDs"))
+        .get_matches();
+    let ids: Vec<i32> = matches.get_many::<i32>("IDS").unwrap().copied().collect();
+
+    let api = connect(&matches)?;
+    if matches.is_present("array") {
+        let mut cancelled = Vec::new();
+        for id in ids {
+            cancelled.extend(api.cancel_array(id).await?);
+        }
+        return print_jobs(&cancelled, matches.is_present("json"));
+    }
+    let cancelled = api.cancel(&ids).await?;
+    for id in ids.iter() {
+        if !cancelled.iter().any(|j| j.id == *id) {
+            eprintln!("Job {} is unknown or already finished", id);
+        }
+    }
+    print_jobs(&cancelled, matches.is_present("json"))
+}
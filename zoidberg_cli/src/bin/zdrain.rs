@@ -0,0 +1,19 @@
+use clap::{arg, App};
+use std::error::Error;
+
+use zoidberg_cli::{common_args, connect, print_workers};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let matches = common_args(App::new("zdrain"))
+        .about("Stops giving jobs to workers and lets them terminate")
+        .arg(arg!(<WORKERS> ... "Workers to drain"))
+        .get_matches();
+
+    let api = connect(&matches)?;
+    let mut drained = Vec::new();
+    for id in matches.get_many::<String>("WORKERS").unwrap() {
+        drained.push(api.drain(id).await?);
+    }
+    print_workers(&drained, matches.is_present("json"))
+}
@@ -0,0 +1,22 @@
+use clap::{arg, value_parser, App};
+use std::error::Error;
+
+use zoidberg_cli::{common_args, connect, print_json};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let matches = common_args(App::new("zoidberg-status"))
+        .about("Prints running, success or failed for every job, use it with --cluster-status")
+        .arg(arg!(<IDS> ... "Jobs to query").value_parser(value_parser!(i32)))
+        .get_matches();
+    let ids: Vec<i32> = matches.get_many::<i32>("IDS").unwrap().copied().collect();
+
+    let states = connect(&matches)?.batch_status(&ids).await?;
+    if matches.is_present("json") {
+        return print_json(&states);
+    }
+    for job in states {
+        println!("{}", job.state);
+    }
+    Ok(())
+}
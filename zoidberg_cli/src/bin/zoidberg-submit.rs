@@ -0,0 +1,48 @@
+use clap::{arg, value_parser, App};
+use serde_json::Value;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use zoidberg_cli::{common_args, connect, print_json};
+use zoidberg_lib::types::Job;
+
+/// Reads the properties that Snakemake writes into the jobscript as
+/// `# properties = {...}`.
+fn read_properties(jobscript: &Path) -> Result<Value, Box<dyn Error>> {
+    let content = std::fs::read_to_string(jobscript)?;
+    let properties = content
+        .lines()
+        .find_map(|l| l.strip_prefix("# properties = "))
+        .ok_or_else(|| format!("no job properties in {}", jobscript.display()))?;
+    Ok(serde_json::from_str(properties)?)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let matches = common_args(App::new("zoidberg-submit"))
+        .about("Submits a Snakemake jobscript, use it with --cluster and prints the job ID")
+        .arg(
+            arg!(<JOBSCRIPT> "Jobscript written by Snakemake").value_parser(value_parser!(PathBuf)),
+        )
+        .get_matches();
+    let jobscript = std::fs::canonicalize(matches.get_one::<PathBuf>("JOBSCRIPT").unwrap())?;
+    let properties = read_properties(&jobscript)?;
+    let resources = &properties["resources"];
+
+    let job = Job {
+        cmd: jobscript.display().to_string(),
+        threads: properties["threads"].as_i64().unwrap_or(1) as i32,
+        memory: resources["mem_mb"].as_i64().unwrap_or(0),
+        disk: resources["disk_mb"].as_i64().unwrap_or(0),
+        // Snakemake gives the runtime in minutes
+        walltime: resources["runtime"].as_i64().map(|m| m * 60),
+        owner: std::env::var("USER").unwrap_or_default(),
+        ..Default::default()
+    };
+    let submitted = connect(&matches)?.submit(&[job]).await?;
+    if matches.is_present("json") {
+        return print_json(&submitted);
+    }
+    println!("{}", submitted[0].id);
+    Ok(())
+}
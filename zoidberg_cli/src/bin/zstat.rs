@@ -0,0 +1,73 @@
+use clap::{arg, value_parser, App};
+use std::error::Error;
+
+use zoidberg_cli::{common_args, connect, print_arrays, print_jobs, print_json, print_workers};
+use zoidberg_lib::types::JobFilter;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let matches = common_args(App::new("zstat"))
+        .about("Shows jobs and workers of a Zoidberg server")
+        .arg(
+            arg!([IDS] ... "Only show these jobs")
+                .required(false)
+                .value_parser(value_parser!(i32)),
+        )
+        .arg(
+            arg!(-s --status <STATUS> "Only show jobs with this status, e.g. running")
+                .required(false)
+                .possible_values([
+                    "submitted",
+                    "running",
+                    "completed",
+                    "failed",
+                    "cancelled",
+                    "timed-out",
+                ]),
+        )
+        .arg(arg!(-u --owner <NAME> "Only show jobs of this owner").required(false))
+        .arg(
+            arg!(--log <ID> "Print the output of a job")
+                .required(false)
+                .value_parser(value_parser!(i32)),
+        )
+        .arg(arg!(-w --workers "List the workers instead of jobs"))
+        .arg(arg!(-a --arrays "Summarize the job arrays with the given IDs instead"))
+        .get_matches();
+    let json = matches.is_present("json");
+    let api = connect(&matches)?;
+
+    if let Some(id) = matches.get_one::<i32>("log") {
+        let log = api.log(*id).await?;
+        if json {
+            return print_json(&log);
+        }
+        print!("{}", log.stdout);
+        eprint!("{}", log.stderr);
+        return Ok(());
+    }
+    if matches.is_present("workers") {
+        return print_workers(&api.workers().await?, json);
+    }
+    if matches.is_present("arrays") {
+        let mut arrays = Vec::new();
+        for id in matches.get_many::<i32>("IDS").into_iter().flatten() {
+            arrays.push(api.array(*id).await?);
+        }
+        return print_arrays(&arrays, json);
+    }
+
+    let filter = JobFilter {
+        status: matches.value_of("status").map(String::from),
+        owner: matches.value_of("owner").map(String::from),
+    };
+    let jobs = match matches.get_many::<i32>("IDS") {
+        Some(ids) => {
+            let ids: Vec<i32> = ids.copied().collect();
+            let jobs = api.status(&ids).await?;
+            jobs.into_iter().filter(|j| filter.matches(j)).collect()
+        }
+        None => api.jobs(&filter).await?,
+    };
+    print_jobs(&jobs, json)
+}
@@ -0,0 +1,257 @@
+use clap::{arg, value_parser, App, AppSettings, Arg};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Read;
+use std::path::PathBuf;
+
+use zoidberg_cli::{common_args, connect, print_jobs};
+use zoidberg_lib::types::{ArrayParams, Dependency, Job, JobArray};
+
+/// One command per line, skipping empty lines and `#` comments.
+fn parse_commands(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+/// Reads the commands of a file, `-` reads stdin.
+fn read_commands(path: &PathBuf) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut content = String::new();
+    if path.as_os_str() == "-" {
+        std::io::stdin().read_to_string(&mut content)?;
+    } else {
+        content = std::fs::read_to_string(path)?;
+    }
+    Ok(parse_commands(&content))
+}
+
+/// Parses an index range like `1-10`, `0-100:5` or `-5--1`.
+fn parse_range(range: &str) -> Result<ArrayParams, String> {
+    let invalid = || format!("Invalid range {}, expected START-END[:STEP]", range);
+    let (bounds, step) = match range.split_once(':') {
+        Some((bounds, step)) => (bounds, step.parse().map_err(|_| invalid())?),
+        None => (range, 1),
+    };
+    // the first character may be the sign of the start
+    let split = bounds
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| *c == '-')
+        .map(|(i, _)| i)
+        .ok_or_else(invalid)?;
+    let (start, end) = (&bounds[..split], &bounds[split + 1..]);
+    Ok(ArrayParams::Range {
+        start: start.parse().map_err(|_| invalid())?,
+        end: end.parse().map_err(|_| invalid())?,
+        step,
+    })
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let matches = common_args(App::new("zsub"))
+        .about("Submits jobs to a Zoidberg server")
+        .setting(AppSettings::TrailingVarArg)
+        .arg(
+            arg!(-f --file <FILE> "Submit every line of this file as a job, - reads stdin")
+                .required(false)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(-a --array <RANGE> "Submit a job array with one task per index of START-END[:STEP], {} in the command is replaced by the index")
+                .required(false)
+                .value_parser(parse_range)
+                .allow_hyphen_values(true)
+                .conflicts_with("file"),
+        )
+        .arg(
+            arg!(--values <VALUES> "Submit a job array with one task per comma separated value, {} in the command is replaced by the value")
+                .required(false)
+                .use_value_delimiter(true)
+                .conflicts_with_all(&["file", "array"]),
+        )
+        .arg(
+            arg!(-j --threads <N> "Threads of every job")
+                .required(false)
+                .value_parser(value_parser!(i32)),
+        )
+        .arg(
+            arg!(-m --memory <MB> "Memory of every job in MB")
+                .required(false)
+                .value_parser(value_parser!(i64)),
+        )
+        .arg(
+            arg!(--disk <MB> "Disk space of every job in MB")
+                .required(false)
+                .value_parser(value_parser!(i64)),
+        )
+        .arg(
+            arg!(-t --walltime <SECONDS> "Kill jobs that run longer than this")
+                .required(false)
+                .value_parser(value_parser!(i64)),
+        )
+        .arg(
+            arg!(--priority <N> "Jobs with higher priority start first")
+                .required(false)
+                .value_parser(value_parser!(i32)),
+        )
+        .arg(
+            arg!(--after <IDS> "Start after these jobs completed")
+                .required(false)
+                .use_value_delimiter(true)
+                .value_parser(value_parser!(i32)),
+        )
+        .arg(arg!(--afterany "Start after the --after jobs finished, even if they failed"))
+        .arg(arg!(--project <NAME> "Project the jobs are accounted to").required(false))
+        .arg(
+            arg!(--"max-retries" <N> "Retries if a job fails")
+                .required(false)
+                .value_parser(value_parser!(i32)),
+        )
+        .arg(
+            arg!(-C --workdir <DIR> "Directory the jobs run in on the worker")
+                .required(false)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(-e --env <"KEY=VALUE"> "Sets an environment variable of the jobs")
+                .required(false)
+                .multiple_occurrences(true),
+        )
+        .arg(arg!(--"clean-env" "Do not pass the environment of the worker to the jobs"))
+        .arg(
+            arg!(--shell <SHELL> "Interpreter that runs the commands, bash by default")
+                .required(false),
+        )
+        .arg(
+            arg!(--"no-shell" "Run the command arguments as program and arguments without a shell")
+                .conflicts_with_all(&["file", "shell"]),
+        )
+        .arg(
+            Arg::new("cmd")
+                .multiple_values(true)
+                .help("Command to submit"),
+        )
+        .get_matches();
+
+    let no_shell = matches.is_present("no-shell");
+    let mut commands = Vec::new();
+    let mut argv = Vec::new();
+    if let Some(cmd) = matches.get_many::<String>("cmd") {
+        let cmd: Vec<String> = cmd.cloned().collect();
+        commands.push(cmd.join(" "));
+        if no_shell {
+            argv = cmd;
+        }
+    }
+    if let Some(path) = matches.get_one::<PathBuf>("file") {
+        commands.extend(read_commands(path)?);
+    }
+    if commands.is_empty() {
+        return Err("Nothing to submit, pass a command or --file".into());
+    }
+
+    let mut env = BTreeMap::new();
+    for var in matches.get_many::<String>("env").into_iter().flatten() {
+        let (key, value) = var
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid --env {}, expected KEY=VALUE", var))?;
+        env.insert(key.to_string(), value.to_string());
+    }
+    // relative to where zsub was called, the worker cannot resolve it
+    let workdir = match matches.get_one::<PathBuf>("workdir") {
+        Some(dir) => Some(std::env::current_dir()?.join(dir).display().to_string()),
+        None => None,
+    };
+
+    let template = Job {
+        argv,
+        shell: matches.value_of("shell").map(String::from),
+        workdir,
+        env,
+        clean_env: matches.is_present("clean-env"),
+        threads: matches.get_one::<i32>("threads").copied().unwrap_or(1),
+        memory: matches.get_one::<i64>("memory").copied().unwrap_or(0),
+        disk: matches.get_one::<i64>("disk").copied().unwrap_or(0),
+        walltime: matches.get_one::<i64>("walltime").copied(),
+        priority: matches.get_one::<i32>("priority").copied().unwrap_or(0),
+        after: matches
+            .get_many::<i32>("after")
+            .map(|ids| ids.copied().collect())
+            .unwrap_or_default(),
+        dependency: match matches.is_present("afterany") {
+            true => Dependency::AfterAny,
+            false => Dependency::AfterOk,
+        },
+        project: matches.value_of("project").map(String::from),
+        max_retries: matches.get_one::<i32>("max-retries").copied(),
+        ..Default::default()
+    };
+    let params = match matches.get_many::<String>("values") {
+        Some(values) => Some(ArrayParams::Values(values.cloned().collect())),
+        None => matches.get_one::<ArrayParams>("array").cloned(),
+    };
+    if let Some(params) = params {
+        let array = JobArray {
+            job: Job {
+                cmd: commands.remove(0),
+                ..template
+            },
+            params,
+        };
+        // checks the template and the parameters before anything is sent
+        for task in array.tasks()? {
+            task.validate()?;
+        }
+        let api = connect(&matches)?;
+        let submitted = api.submit_array(&array).await?;
+        return print_jobs(&submitted, matches.is_present("json"));
+    }
+    let jobs: Vec<Job> = commands
+        .into_iter()
+        .map(|cmd| Job {
+            cmd,
+            ..template.clone()
+        })
+        .collect();
+    for job in jobs.iter() {
+        job.validate()?;
+    }
+
+    let api = connect(&matches)?;
+    let submitted = api.submit(&jobs).await?;
+    print_jobs(&submitted, matches.is_present("json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(range: &str) -> Option<(i64, i64, i64)> {
+        match parse_range(range) {
+            Ok(ArrayParams::Range { start, end, step }) => Some((start, end, step)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(range("1-10"), Some((1, 10, 1)));
+        assert_eq!(range("0-100:5"), Some((0, 100, 5)));
+        assert_eq!(range("-5--1"), Some((-5, -1, 1)));
+        assert_eq!(range("-5-5:2"), Some((-5, 5, 2)));
+        for invalid in ["", "5", "-5", "1-", "a-b", "1-10:", "1-10:x", "1--"] {
+            assert_eq!(range(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        let content = "echo 1\n\n  # comment\n  echo 2  \n\t\n#echo 3\n";
+        assert_eq!(parse_commands(content), vec!["echo 1", "echo 2"]);
+        assert!(parse_commands("").is_empty());
+    }
+}
@@ -0,0 +1,42 @@
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Settings of the command line tools, read from `$ZOIDBERG_CONFIG` or
+/// `~/.config/zoidberg/cli.toml`.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address of the server, e.g. `https://zoidberg.example.org:8080`.
+    pub server: Option<String>,
+    /// Token to authenticate with, `$ZOIDBERG_TOKEN` takes precedence.
+    pub token: Option<String>,
+    /// Additional CA certificate to verify the server with.
+    pub ca_cert: Option<PathBuf>,
+}
+
+impl Config {
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        toml::from_str(&content).map_err(|e| format!("invalid config {}: {}", path.display(), e))
+    }
+
+    /// Reads the config file if there is one.
+    pub fn load() -> Result<Self, String> {
+        if let Some(path) = std::env::var_os("ZOIDBERG_CONFIG") {
+            return Config::from_file(Path::new(&path));
+        }
+        let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) => PathBuf::from(dir),
+            None => match std::env::var_os("HOME") {
+                Some(home) => PathBuf::from(home).join(".config"),
+                None => return Ok(Config::default()),
+            },
+        };
+        let path = dir.join("zoidberg").join("cli.toml");
+        match path.is_file() {
+            true => Config::from_file(&path),
+            false => Ok(Config::default()),
+        }
+    }
+}
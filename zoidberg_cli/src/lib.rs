@@ -0,0 +1,183 @@
+//! Shared code of the command line tools.
+
+use chrono::Utc;
+use clap::{arg, value_parser, App, ArgMatches};
+use serde::Serialize;
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use zoidberg_lib::client::{Client, ClientBuilder};
+use zoidberg_lib::types::{ArrayStatus, Job, Worker};
+
+mod config;
+
+pub use config::Config;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Adds the options that are shared by all tools.
+pub fn common_args(app: App<'static>) -> App<'static> {
+    app.version(VERSION)
+        .arg(
+            arg!(--server <URL> "Zoidberg server address [default: $ZOIDBERG_SERVER or config]")
+                .required(false),
+        )
+        .arg(
+            arg!(--"ca-cert" <FILE> "PEM encoded CA certificate or bundle to verify the server with")
+                .required(false)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(arg!(--json "Print JSON instead of a table"))
+}
+
+/// Connects to the server given on the command line, in `$ZOIDBERG_SERVER`
+/// or in the config file, with the token in `$ZOIDBERG_TOKEN` or the config
+/// file.
+pub fn connect(matches: &ArgMatches) -> Result<Client, Box<dyn Error>> {
+    let config = Config::load()?;
+    let server = match matches.value_of("server") {
+        Some(server) => server.to_string(),
+        None => std::env::var("ZOIDBERG_SERVER")
+            .ok()
+            .or(config.server)
+            .unwrap_or_else(|| String::from("http://localhost:8080")),
+    };
+    let token = std::env::var("ZOIDBERG_TOKEN")
+        .or_else(|_| std::env::var("ZOIDBERG_SECRET"))
+        .ok()
+        .or(config.token)
+        .ok_or("Please set the $ZOIDBERG_TOKEN environment variable")?;
+
+    let mut builder = ClientBuilder::new(&server, &token)
+        .timeout(Duration::from_secs(30))
+        .retries(3);
+    if let Some(path) = matches
+        .get_one::<PathBuf>("ca-cert")
+        .or(config.ca_cert.as_ref())
+    {
+        builder = builder.ca_cert(&std::fs::read(path)?)?;
+    }
+    Ok(builder.build()?)
+}
+
+pub fn print_json<T: Serialize>(value: &T) -> Result<(), Box<dyn Error>> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Prints the rows with every column as wide as its widest cell.
+pub fn print_table(header: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let header: Vec<String> = header.iter().map(|h| h.to_string()).collect();
+    for row in std::iter::once(&header).chain(rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+fn duration(seconds: i64) -> String {
+    match seconds {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m{:02}s", s / 60, s % 60),
+        s => format!("{}h{:02}m", s / 3600, s % 3600 / 60),
+    }
+}
+
+pub fn print_jobs(jobs: &[Job], json: bool) -> Result<(), Box<dyn Error>> {
+    if json {
+        return print_json(&jobs);
+    }
+    let now = Utc::now().timestamp();
+    let rows: Vec<Vec<String>> = jobs
+        .iter()
+        .map(|j| {
+            vec![
+                j.id.to_string(),
+                match (j.array_id, j.array_index) {
+                    (Some(array), Some(index)) => format!("{}[{}]", array, index),
+                    _ => String::new(),
+                },
+                j.status.name().to_string(),
+                j.owner.clone(),
+                j.threads.to_string(),
+                j.worker.clone().unwrap_or_default(),
+                j.runtime(now).map(duration).unwrap_or_default(),
+                j.reason.clone().unwrap_or_default(),
+                j.cmd.clone(),
+            ]
+        })
+        .collect();
+    print_table(
+        &[
+            "ID", "ARRAY", "STATUS", "OWNER", "THREADS", "WORKER", "RUNTIME", "REASON", "CMD",
+        ],
+        &rows,
+    );
+    Ok(())
+}
+
+pub fn print_arrays(arrays: &[ArrayStatus], json: bool) -> Result<(), Box<dyn Error>> {
+    if json {
+        return print_json(&arrays);
+    }
+    let rows: Vec<Vec<String>> = arrays
+        .iter()
+        .map(|a| {
+            vec![
+                a.id.to_string(),
+                a.tasks.to_string(),
+                a.submitted.to_string(),
+                a.running.to_string(),
+                a.completed.to_string(),
+                a.failed.to_string(),
+                a.cancelled.to_string(),
+                a.timed_out.to_string(),
+            ]
+        })
+        .collect();
+    print_table(
+        &[
+            "ARRAY",
+            "TASKS",
+            "SUBMITTED",
+            "RUNNING",
+            "COMPLETED",
+            "FAILED",
+            "CANCELLED",
+            "TIMED-OUT",
+        ],
+        &rows,
+    );
+    Ok(())
+}
+
+pub fn print_workers(workers: &[Worker], json: bool) -> Result<(), Box<dyn Error>> {
+    if json {
+        return print_json(&workers);
+    }
+    let now = Utc::now().timestamp();
+    let rows: Vec<Vec<String>> = workers
+        .iter()
+        .map(|w| {
+            vec![
+                w.id.clone(),
+                w.last_heartbeat
+                    .map(|t| format!("{} ago", duration(now - t)))
+                    .unwrap_or_else(|| String::from("never")),
+                String::from(if w.draining { "draining" } else { "active" }),
+            ]
+        })
+        .collect();
+    print_table(&["ID", "LAST HEARTBEAT", "STATE"], &rows);
+    Ok(())
+}
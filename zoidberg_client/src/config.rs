@@ -0,0 +1,230 @@
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// What happens to running jobs when the worker is asked to stop.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OnShutdown {
+    /// Let them finish and report their status.
+    Wait,
+    /// Terminate them, the server retries them if they have retries left.
+    Kill,
+}
+
+/// Worker configuration, read from a TOML file and overridden by command
+/// line options. Times are given in seconds.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address of the server, e.g. `https://zoidberg.example.org:8080`.
+    pub server: Option<String>,
+    pub threads: i32,
+    /// Memory in MB, unlimited if not set.
+    pub memory: Option<i64>,
+    /// Disk space in MB, unlimited if not set.
+    pub disk: Option<i64>,
+    /// Additional CA certificate to verify the server with.
+    pub ca_cert: Option<PathBuf>,
+    /// Shortest pause between two fetches when no job was queued.
+    pub poll_interval: f64,
+    /// Seconds the server may hold a fetch until a job is queued, limited
+    /// by the server. 0 turns long-polling off.
+    pub fetch_wait: u64,
+    /// Most jobs fetched at once, limited by the free threads.
+    pub fetch_batch: u32,
+    /// Pause between heartbeats, has to stay below the worker timeout of
+    /// the server.
+    pub heartbeat_interval: f64,
+    /// Timeout of a single HTTP request.
+    pub request_timeout: f64,
+    /// Retries of requests that failed with a connection or server error.
+    pub request_retries: u32,
+    /// Shortest pause after a failed fetch, it grows exponentially.
+    pub backoff_min: f64,
+    /// Longest pause after a failed fetch.
+    pub backoff_max: f64,
+    /// Failed fetches in a row after which the worker gives up.
+    pub max_fetch_failures: u32,
+    /// Never give up when the server cannot be reached.
+    pub retry_forever: bool,
+    /// Time between SIGTERM and SIGKILL when a job exceeds its walltime.
+    pub kill_grace: f64,
+    /// Whether SIGTERM or SIGINT waits for running jobs or kills them.
+    pub on_shutdown: OnShutdown,
+    /// Longest wait for running jobs when shutting down, they are killed
+    /// afterwards. Waits as long as it takes if not set.
+    pub shutdown_timeout: Option<f64>,
+    /// Directory below which every job gets a scratch directory that is
+    /// removed when the job ended, the system temporary directory if not
+    /// set.
+    pub scratch_dir: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            server: None,
+            threads: 1,
+            memory: None,
+            disk: None,
+            ca_cert: None,
+            poll_interval: 1.0,
+            fetch_wait: 30,
+            fetch_batch: 16,
+            heartbeat_interval: 30.0,
+            request_timeout: 15.0,
+            request_retries: 3,
+            backoff_min: 5.0,
+            backoff_max: 300.0,
+            max_fetch_failures: 3,
+            retry_forever: false,
+            kill_grace: 10.0,
+            on_shutdown: OnShutdown::Wait,
+            shutdown_timeout: None,
+            scratch_dir: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        toml::from_str(&content).map_err(|e| format!("invalid config {}: {}", path.display(), e))
+    }
+
+    /// `$XDG_CONFIG_HOME/zoidberg/client.toml`, falling back to `~/.config`.
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(dir.join("zoidberg").join("client.toml"))
+    }
+
+    /// Checks the settings and returns all problems that were found.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        if self.server.is_none() {
+            errors.push(String::from(
+                "no server set, pass it as argument, in $ZOIDBERG_SERVER or the config file",
+            ));
+        }
+        if self.threads < 1 {
+            errors.push(String::from("threads must be positive"));
+        }
+        if self.fetch_batch < 1 {
+            errors.push(String::from("fetch_batch must be positive"));
+        }
+        for (name, value) in [
+            ("poll_interval", self.poll_interval),
+            ("heartbeat_interval", self.heartbeat_interval),
+            ("request_timeout", self.request_timeout),
+            ("backoff_min", self.backoff_min),
+            ("backoff_max", self.backoff_max),
+            ("kill_grace", self.kill_grace),
+        ] {
+            if !value.is_finite() || value <= 0.0 {
+                errors.push(format!("{} must be a positive number of seconds", name));
+            }
+        }
+        if let Some(t) = self.shutdown_timeout {
+            if !t.is_finite() || t <= 0.0 {
+                errors.push(String::from(
+                    "shutdown_timeout must be a positive number of seconds",
+                ));
+            }
+        }
+        if self.backoff_min > self.backoff_max {
+            errors.push(String::from("backoff_min must not exceed backoff_max"));
+        }
+        if self.max_fetch_failures == 0 && !self.retry_forever {
+            errors.push(String::from(
+                "max_fetch_failures must be positive, use retry_forever to never give up",
+            ));
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_secs_f64(self.poll_interval)
+    }
+
+    pub fn heartbeat_interval(&self) -> Duration {
+        Duration::from_secs_f64(self.heartbeat_interval)
+    }
+
+    pub fn request_timeout(&self) -> Duration {
+        Duration::from_secs_f64(self.request_timeout)
+    }
+
+    pub fn backoff_min(&self) -> Duration {
+        Duration::from_secs_f64(self.backoff_min)
+    }
+
+    pub fn backoff_max(&self) -> Duration {
+        Duration::from_secs_f64(self.backoff_max)
+    }
+
+    pub fn kill_grace(&self) -> Duration {
+        Duration::from_secs_f64(self.kill_grace)
+    }
+
+    pub fn shutdown_timeout(&self) -> Option<Duration> {
+        self.shutdown_timeout.map(Duration::from_secs_f64)
+    }
+
+    pub fn scratch_dir(&self) -> PathBuf {
+        self.scratch_dir.clone().unwrap_or_else(std::env::temp_dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_example_config() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("config.example.toml");
+        let config = Config::from_file(&path).unwrap();
+        assert_eq!(config.server.as_deref(), Some("http://localhost:8080"));
+        assert_eq!(config.on_shutdown, OnShutdown::Wait);
+        assert_eq!(config.fetch_batch, 16);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validation() {
+        let config: Config = toml::from_str(
+            r#"
+            threads = 0
+            backoff_min = 10.0
+            backoff_max = 5.0
+            shutdown_timeout = -1.0
+            on_shutdown = "kill"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.on_shutdown, OnShutdown::Kill);
+        // no server, no threads, backoff bounds and shutdown timeout
+        let errors = config.validate().unwrap_err();
+        assert_eq!(errors.len(), 4);
+
+        let config: Config = toml::from_str(
+            r#"
+            server = "http://localhost:8080"
+            max_fetch_failures = 0
+            retry_forever = true
+            "#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+
+        assert!(toml::from_str::<Config>("unknown = 1").is_err());
+        assert!(toml::from_str::<Config>("on_shutdown = \"maybe\"").is_err());
+    }
+}
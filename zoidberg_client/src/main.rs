@@ -1,118 +1,321 @@
+use chrono::Utc;
 use clap::{arg, value_parser, App, Arg};
 use env_logger::Env;
 use futures::future::{AbortHandle, Abortable};
-use log;
-use reqwest::{header, Client, ClientBuilder};
+use retry_policies::policies::ExponentialBackoff;
+use retry_policies::{RetryDecision, RetryPolicy};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
-use std::process::Stdio;
-use std::sync::Arc;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{Output, Stdio};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tokio::{process::Command, time};
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio::{process::Command, sync::Notify, time};
 
-use zoidberg_lib::types::{
-    FetchRequest, FetchResponse, Heartbeat, Job, RegisterResponse, Status, Update,
-};
+use zoidberg_lib::client::{self, Client, ClientBuilder};
+use zoidberg_lib::types::{FetchRequest, FetchResponse, Job, Log, Resources, Status, Update};
+
+mod config;
+
+use config::{Config, OnShutdown};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
-fn build_client(secret: &str) -> Client {
-    let cookie = secret.to_string();
+// only the end of the output is sent to the server to keep requests small
+const MAX_LOG_SIZE: usize = 512 * 1024;
 
-    let mut headers = header::HeaderMap::new();
-    headers.insert(
-        "cookie",
-        header::HeaderValue::from_str(&cookie)
-            .unwrap_or_else(|_| panic!("invalid header value {}", &cookie)),
-    );
+fn tail(output: &[u8]) -> String {
+    let start = output.len().saturating_sub(MAX_LOG_SIZE);
+    String::from_utf8_lossy(&output[start..]).into_owned()
+}
+
+/// Process groups of the running jobs, by job ID.
+#[derive(Debug, Default)]
+struct Processes {
+    groups: Mutex<HashMap<i32, i32>>,
+    /// Jobs that were terminated because the worker shuts down.
+    interrupted: Mutex<HashSet<i32>>,
+}
+
+impl Processes {
+    fn insert(&self, job: i32, pgid: i32) {
+        self.groups.lock().unwrap().insert(job, pgid);
+    }
+
+    fn remove(&self, job: i32) {
+        self.groups.lock().unwrap().remove(&job);
+    }
+
+    fn signal(&self, job: i32, signal: i32) {
+        if let Some(pgid) = self.groups.lock().unwrap().get(&job) {
+            unsafe {
+                libc::killpg(*pgid, signal);
+            }
+        }
+    }
 
-    ClientBuilder::new()
-        .timeout(Duration::from_secs(15))
-        .default_headers(headers)
-        .build()
-        .expect("Could not create client")
+    fn kill(&self, job: i32) {
+        log::info!("Killing cancelled job {}", job);
+        self.signal(job, libc::SIGKILL);
+    }
+
+    /// Sends `signal` to all running jobs and remembers them as interrupted.
+    fn interrupt(&self, signal: i32) {
+        let groups = self.groups.lock().unwrap();
+        let mut interrupted = self.interrupted.lock().unwrap();
+        for (job, pgid) in groups.iter() {
+            log::info!("Interrupting job {}", job);
+            interrupted.insert(*job);
+            unsafe {
+                libc::killpg(*pgid, signal);
+            }
+        }
+    }
+
+    fn was_interrupted(&self, job: i32) -> bool {
+        self.interrupted.lock().unwrap().contains(&job)
+    }
 }
 
-#[derive(Debug, Clone)]
+/// SIGTERM and SIGINT, which make the worker shut down.
+struct Signals {
+    term: Signal,
+    int: Signal,
+}
+
+impl Signals {
+    fn new() -> std::io::Result<Self> {
+        Ok(Signals {
+            term: signal(SignalKind::terminate())?,
+            int: signal(SignalKind::interrupt())?,
+        })
+    }
+
+    /// Waits for the next signal and returns its name.
+    async fn recv(&mut self) -> &'static str {
+        tokio::select! {
+            _ = self.term.recv() => "SIGTERM",
+            _ = self.int.recv() => "SIGINT",
+        }
+    }
+}
+
+#[derive(Debug)]
 struct Worker {
     id: String,
-    secret: String,
-    server: String,
-    threads: i32,
+    api: Client,
+    resources: Resources,
+    /// Resources that are not used by running jobs.
+    free: Mutex<Resources>,
+    /// Notified whenever a job finished.
+    finished: Notify,
+    processes: Processes,
+    /// Time between SIGTERM and SIGKILL when a job exceeds its walltime.
+    kill_grace: Duration,
+    /// Seconds the server may hold a fetch until a job is queued.
+    fetch_wait: u64,
+    /// Most jobs asked for in a single fetch.
+    fetch_batch: u32,
+    /// Contains the scratch directories of the running jobs.
+    scratch: PathBuf,
 }
 
 impl Worker {
-    async fn new(server: &str, secret: &str, threads: i32) -> Result<Worker, Box<dyn Error>> {
-        let res = build_client(secret)
-            .get(format!("{}/register", server))
-            .send()
-            .await?;
-
-        let body = res.text().await?;
-        let r: RegisterResponse = serde_json::from_str(&body)?;
-        log::info!("registered worker with id: {}", &r.id);
+    async fn new(
+        api: Client,
+        resources: Resources,
+        kill_grace: Duration,
+        fetch_wait: u64,
+        fetch_batch: u32,
+        scratch_dir: &Path,
+    ) -> Result<Worker, client::Error> {
+        let id = api.register().await?;
+        log::info!("registered worker with id: {}", &id);
         Ok(Worker {
-            id: r.id,
-            secret: secret.to_string(),
-            server: server.to_string(),
-            threads,
+            scratch: scratch_dir.join(format!("zoidberg-{}", id)),
+            id,
+            api,
+            resources,
+            free: Mutex::new(resources),
+            finished: Notify::new(),
+            processes: Processes::default(),
+            kill_grace,
+            fetch_wait,
+            fetch_batch,
         })
     }
 
-    async fn update(&self, jobs: &[Job]) -> Result<(), Box<dyn Error>> {
+    /// Scratch directory of a job.
+    fn scratch(&self, job: &Job) -> PathBuf {
+        self.scratch.join(format!("job-{}", job.id))
+    }
+
+    async fn update(&self, jobs: &[Job]) -> Result<(), client::Error> {
         let updates: Vec<Update> = jobs
             .iter()
             .map(|job| Update {
                 worker: self.id.clone(),
                 job: job.id,
                 status: job.status.clone(),
+                exit_code: job.exit_code,
+                signal: job.signal,
+                reason: job.reason.clone(),
             })
             .collect();
 
-        let body = build_client(&self.secret)
-            .post(format!("{}/update", self.server))
-            .json(&updates)
-            .send()
-            .await?
-            .text()
-            .await?;
-
+        let body = self.api.update(&updates).await?;
         log::info!("Body: {}", body);
         Ok(())
     }
 
-    async fn fetch(&self) -> Result<FetchResponse, Box<dyn Error>> {
-        let res = build_client(&self.secret)
-            .post(format!("{}/fetch", self.server))
-            .json(&FetchRequest {
-                worker_id: self.id.clone(),
-                threads: self.threads,
+    async fn log(&self, job: &Job, output: &Output) -> Result<(), client::Error> {
+        self.api
+            .upload_log(&Log {
+                worker: self.id.clone(),
+                job: job.id,
+                stdout: tail(&output.stdout),
+                stderr: tail(&output.stderr),
             })
-            .send()
-            .await?;
-        let body = res.text().await?;
-        let resp: FetchResponse = serde_json::from_str(&body)?;
-        Ok(resp)
-    }
-
-    async fn heartbeat(&self) {
-        let _ = build_client(&self.secret)
-            .post(format!("{}/heartbeat", self.server))
-            .json(&Heartbeat {
-                id: self.id.clone(),
+            .await
+    }
+
+    async fn fetch(&self) -> Result<FetchResponse, client::Error> {
+        let free = *self.free.lock().unwrap();
+        // every job occupies at least one thread
+        let max_jobs = self.fetch_batch.min(free.threads.max(0) as u32);
+        self.api
+            .fetch(&FetchRequest {
+                worker_id: self.id.clone(),
+                free,
+                wait: Some(self.fetch_wait),
+                max_jobs: Some(max_jobs),
             })
-            .send()
-            .await;
+            .await
+    }
+
+    async fn heartbeat(&self) -> Result<(), client::Error> {
+        let resp = self.api.heartbeat(&self.id).await?;
+        for job in resp.cancel {
+            self.processes.kill(job);
+        }
+        Ok(())
+    }
+
+    /// Waits until no job is running anymore.
+    async fn idle(&self) {
+        while self.free.lock().unwrap().threads < self.resources.threads {
+            self.finished.notified().await;
+        }
+    }
+
+    /// Terminates the running jobs and kills them if they are still running
+    /// after the grace period.
+    async fn interrupt(&self) {
+        self.processes.interrupt(libc::SIGTERM);
+        if time::timeout(self.kill_grace, self.idle()).await.is_err() {
+            self.processes.interrupt(libc::SIGKILL);
+            self.idle().await;
+        }
     }
 }
 
-async fn run(job: &Job) -> Result<(), Box<dyn Error>> {
-    let output = Command::new("bash")
-        .arg("-c")
-        .arg(&job.cmd)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output();
-    let output = output.await?;
+/// Why the job cannot run on this worker, if it cannot.
+fn check(job: &Job) -> Option<String> {
+    if let Some(dir) = &job.workdir {
+        if !Path::new(dir).is_dir() {
+            return Some(format!(
+                "working directory {} does not exist on worker",
+                dir
+            ));
+        }
+    }
+    None
+}
+
+/// How the process of a job ended.
+struct Outcome {
+    output: Output,
+    /// Whether it was killed for exceeding its walltime.
+    timed_out: bool,
+}
+
+async fn run(job: &Job, worker: &Worker) -> Result<Outcome, Box<dyn Error>> {
+    let mut command = match job.argv.split_first() {
+        Some((program, args)) => {
+            let mut command = Command::new(program);
+            command.args(args);
+            command
+        }
+        None => {
+            let mut command = Command::new(job.shell.as_deref().unwrap_or("bash"));
+            command.arg("-c").arg(&job.cmd);
+            command
+        }
+    };
+    if job.clean_env {
+        command.env_clear();
+    }
+    // jobs that do not request threads still get one
+    let threads = job.threads.max(1).to_string();
+    command
+        .env("ZOIDBERG_JOB_ID", job.id.to_string())
+        .env("ZOIDBERG_WORKER_ID", &worker.id)
+        .env("ZOIDBERG_SERVER", worker.api.server())
+        .env("ZOIDBERG_THREADS", &threads)
+        .env("ZOIDBERG_SCRATCH", worker.scratch(job))
+        .env("OMP_NUM_THREADS", &threads)
+        .env("MKL_NUM_THREADS", &threads)
+        .env("OPENBLAS_NUM_THREADS", &threads);
+    if let (Some(array), Some(index)) = (job.array_id, job.array_index) {
+        command.env("ZOIDBERG_ARRAY_ID", array.to_string());
+        command.env("ZOIDBERG_ARRAY_INDEX", index.to_string());
+    }
+    command.envs(&job.env);
+    if let Some(dir) = &job.workdir {
+        command.current_dir(dir);
+    }
+    command.stdout(Stdio::piped()).stderr(Stdio::piped());
+    // run the job in its own process group, so that it can be killed
+    // together with all of its children
+    unsafe {
+        command.pre_exec(|| match libc::setpgid(0, 0) {
+            0 => Ok(()),
+            _ => Err(std::io::Error::last_os_error()),
+        });
+    }
+    let child = command.spawn()?;
+    let processes = &worker.processes;
+    if let Some(pid) = child.id() {
+        processes.insert(job.id, pid as i32);
+    }
+    let output = child.wait_with_output();
+    tokio::pin!(output);
+    let mut timed_out = false;
+    let output = match job.walltime {
+        None => output.await,
+        Some(walltime) => {
+            match time::timeout(Duration::from_secs(walltime as u64), &mut output).await {
+                Ok(output) => output,
+                Err(_) => {
+                    // give the job a chance to clean up before it is killed
+                    log::info!("Job {} exceeded its walltime of {} s", job.id, walltime);
+                    timed_out = true;
+                    processes.signal(job.id, libc::SIGTERM);
+                    match time::timeout(worker.kill_grace, &mut output).await {
+                        Ok(output) => output,
+                        Err(_) => {
+                            processes.signal(job.id, libc::SIGKILL);
+                            output.await
+                        }
+                    }
+                }
+            }
+        }
+    };
+    processes.remove(job.id);
+    let output = output?;
 
     log::info!(
         "command: {}\nstdout: {}\nstderr: {}",
@@ -120,9 +323,130 @@ async fn run(job: &Job) -> Result<(), Box<dyn Error>> {
         String::from_utf8_lossy(&output.stdout),
         String::from_utf8_lossy(&output.stderr)
     );
-    match output.status.success() {
-        true => Ok(()),
-        false => Err(Box::from("Job failed")),
+    Ok(Outcome { output, timed_out })
+}
+
+/// Runs a job, reports the outcome to the server and frees its resources.
+async fn process(client: Arc<Worker>, mut job: Job) {
+    let scratch = client.scratch(&job);
+    let problem = check(&job).or_else(|| {
+        std::fs::create_dir_all(&scratch).err().map(|e| {
+            format!(
+                "could not create scratch directory {}: {}",
+                scratch.display(),
+                e
+            )
+        })
+    });
+    let output = match problem {
+        Some(reason) => {
+            log::error!("Cannot run job {}: {}", job.id, reason);
+            job.status = Status::Failed;
+            job.reason = Some(reason);
+            None
+        }
+        None => match run(&job, &client).await {
+            Ok(outcome) => Some(outcome),
+            Err(error) => {
+                log::error!("Could not run job {}: {}", job.id, error);
+                job.status = Status::Failed;
+                job.reason = Some(format!("could not run command: {}", error));
+                None
+            }
+        },
+    };
+    if let Err(error) = tokio::fs::remove_dir_all(&scratch).await {
+        if error.kind() != std::io::ErrorKind::NotFound {
+            log::error!(
+                "Could not remove scratch directory of job {}: {}",
+                job.id,
+                error
+            );
+        }
+    }
+    if let Some(Outcome { output, timed_out }) = output {
+        if let Err(error) = client.log(&job, &output).await {
+            log::error!("Could not upload log of job {}: {}", job.id, error);
+        }
+        job.exit_code = output.status.code();
+        job.signal = output.status.signal();
+        if timed_out {
+            job.status = Status::TimedOut;
+            job.reason = Some(format!(
+                "exceeded walltime of {} s",
+                job.walltime.unwrap_or_default()
+            ));
+        } else if output.status.success() {
+            job.status = Status::Completed;
+        } else {
+            job.status = Status::Failed;
+            job.reason = Some(match (job.exit_code, job.signal) {
+                (_, Some(signal)) => format!("killed by signal {}", signal),
+                (Some(code), _) => format!("exited with code {}", code),
+                _ => String::from("exited abnormally"),
+            });
+        }
+    }
+    if client.processes.was_interrupted(job.id) {
+        // not reported, the server requeues the job once the worker
+        // deregistered
+        log::info!("Job {} was interrupted by worker shutdown", job.id);
+    } else if let Err(error) = client.update(&[job.clone()]).await {
+        log::info!("Could not update job: {}", error);
+    }
+    client.free.lock().unwrap().release(&job);
+    client.finished.notify_one();
+}
+
+/// Fetches and starts jobs until the server tells the worker to terminate.
+async fn fetch_jobs(worker: &Arc<Worker>, pause: Duration, backoff: &ExponentialBackoff) {
+    let mut fail_counter = 0;
+    loop {
+        if worker.free.lock().unwrap().threads < 1 {
+            worker.finished.notified().await;
+            continue;
+        }
+        let started = time::Instant::now();
+        let jobs = match worker.fetch().await {
+            Ok(fetch) => {
+                fail_counter = 0;
+                match fetch {
+                    FetchResponse::Nop => {
+                        // the server may not support waiting for jobs
+                        time::sleep(pause.saturating_sub(started.elapsed())).await;
+                        continue;
+                    }
+                    FetchResponse::Terminate(m) => {
+                        log::info!("Terminate worker: {}", m);
+                        return;
+                    }
+                    FetchResponse::Jobs(jobs) => jobs,
+                }
+            }
+            Err(error) => {
+                log::error!("failed to fetch new jobs: {}", error);
+                match backoff.should_retry(fail_counter) {
+                    RetryDecision::Retry { execute_after } => {
+                        fail_counter = fail_counter.saturating_add(1);
+                        time::sleep((execute_after - Utc::now()).to_std().unwrap_or_default())
+                            .await;
+                        continue;
+                    }
+                    RetryDecision::DoNotRetry => {
+                        log::error!(
+                            "failed to fetch {} times, assume that server crashed and exit",
+                            fail_counter + 1
+                        );
+                        std::process::exit(1);
+                    }
+                }
+            }
+        };
+
+        for job in jobs {
+            worker.free.lock().unwrap().take(&job);
+            tokio::spawn(process(Arc::clone(worker), job));
+        }
     }
 }
 
@@ -138,35 +462,216 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 .takes_value(true)
                 .help("Set Zoidberg server address"),
         )
+        .arg(
+            arg!(-c --config <FILE> "Read the configuration from this TOML file")
+                .required(false)
+                .value_parser(value_parser!(PathBuf)),
+        )
         .arg(
             arg!(-j --threads <VALUE> "Sets number of threads")
                 .required(false)
                 .value_parser(value_parser!(i32)),
         )
+        .arg(
+            arg!(-m --memory <MB> "Sets available memory in MB")
+                .required(false)
+                .value_parser(value_parser!(i64)),
+        )
+        .arg(
+            arg!(--disk <MB> "Sets available disk space in MB")
+                .required(false)
+                .value_parser(value_parser!(i64)),
+        )
+        .arg(
+            arg!(--"ca-cert" <FILE> "PEM encoded CA certificate or bundle to verify the server with")
+                .required(false)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(--"poll-interval" <SECONDS> "Shortest pause between fetches when no job was queued")
+                .required(false)
+                .value_parser(value_parser!(f64)),
+        )
+        .arg(
+            arg!(--"fetch-wait" <SECONDS> "Time the server may hold a request for a job, 0 polls")
+                .required(false)
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"fetch-batch" <N> "Most jobs fetched at once, limited by the free threads")
+                .required(false)
+                .value_parser(value_parser!(u32)),
+        )
+        .arg(
+            arg!(--"heartbeat-interval" <SECONDS> "Pause between heartbeats")
+                .required(false)
+                .value_parser(value_parser!(f64)),
+        )
+        .arg(
+            arg!(--"request-timeout" <SECONDS> "Timeout of a single HTTP request")
+                .required(false)
+                .value_parser(value_parser!(f64)),
+        )
+        .arg(
+            arg!(--"request-retries" <N> "Retries of requests that failed transiently")
+                .required(false)
+                .value_parser(value_parser!(u32)),
+        )
+        .arg(
+            arg!(--"backoff-min" <SECONDS> "Shortest pause after a failed fetch")
+                .required(false)
+                .value_parser(value_parser!(f64)),
+        )
+        .arg(
+            arg!(--"backoff-max" <SECONDS> "Longest pause after a failed fetch")
+                .required(false)
+                .value_parser(value_parser!(f64)),
+        )
+        .arg(
+            arg!(--"max-fetch-failures" <N> "Failed fetches in a row after which the worker exits")
+                .required(false)
+                .value_parser(value_parser!(u32)),
+        )
+        .arg(arg!(--"retry-forever" "Never exit when the server cannot be reached"))
+        .arg(
+            arg!(--"on-shutdown" <MODE> "Wait for or kill running jobs on SIGTERM and SIGINT")
+                .required(false)
+                .possible_values(["wait", "kill"]),
+        )
+        .arg(
+            arg!(--"shutdown-timeout" <SECONDS> "Kill running jobs that do not finish this long after SIGTERM")
+                .required(false)
+                .value_parser(value_parser!(f64)),
+        )
+        .arg(
+            arg!(--"scratch-dir" <DIR> "Directory below which jobs get their scratch directories")
+                .required(false)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(--"kill-grace" <SECONDS> "Time between SIGTERM and SIGKILL for jobs exceeding their walltime")
+                .required(false)
+                .value_parser(value_parser!(f64)),
+        )
         .get_matches();
-    let threads: i32 = if let Some(t) = matches.get_one::<i32>("threads") {
-        *t
-    } else {
-        1
-    };
 
-    let secret = std::env::var("ZOIDBERG_SECRET").unwrap_or_else(|_| {
-        eprintln!("Please set the $ZOIDBERG_SECRET environment variable");
+    let path = match matches.get_one::<PathBuf>("config") {
+        Some(path) => Some(path.clone()),
+        None => Config::default_path().filter(|p| p.is_file()),
+    };
+    let mut config = match path {
+        Some(path) => Config::from_file(&path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }),
+        None => Config::default(),
+    };
+    // command line options take precedence over the config file
+    if let Ok(server) = std::env::var("ZOIDBERG_SERVER") {
+        config.server = Some(server);
+    }
+    if let Some(server) = matches.value_of("server") {
+        config.server = Some(server.to_string());
+    }
+    if let Some(threads) = matches.get_one::<i32>("threads") {
+        config.threads = *threads;
+    }
+    if let Some(memory) = matches.get_one::<i64>("memory") {
+        config.memory = Some(*memory);
+    }
+    if let Some(disk) = matches.get_one::<i64>("disk") {
+        config.disk = Some(*disk);
+    }
+    if let Some(path) = matches.get_one::<PathBuf>("ca-cert") {
+        config.ca_cert = Some(path.clone());
+    }
+    for (name, value) in [
+        ("poll-interval", &mut config.poll_interval),
+        ("heartbeat-interval", &mut config.heartbeat_interval),
+        ("request-timeout", &mut config.request_timeout),
+        ("backoff-min", &mut config.backoff_min),
+        ("backoff-max", &mut config.backoff_max),
+        ("kill-grace", &mut config.kill_grace),
+    ] {
+        if let Some(v) = matches.get_one::<f64>(name) {
+            *value = *v;
+        }
+    }
+    if let Some(wait) = matches.get_one::<u64>("fetch-wait") {
+        config.fetch_wait = *wait;
+    }
+    if let Some(n) = matches.get_one::<u32>("fetch-batch") {
+        config.fetch_batch = *n;
+    }
+    if let Some(n) = matches.get_one::<u32>("request-retries") {
+        config.request_retries = *n;
+    }
+    if let Some(n) = matches.get_one::<u32>("max-fetch-failures") {
+        config.max_fetch_failures = *n;
+    }
+    if matches.is_present("retry-forever") {
+        config.retry_forever = true;
+    }
+    match matches.value_of("on-shutdown") {
+        Some("wait") => config.on_shutdown = OnShutdown::Wait,
+        Some("kill") => config.on_shutdown = OnShutdown::Kill,
+        _ => {}
+    }
+    if let Some(t) = matches.get_one::<f64>("shutdown-timeout") {
+        config.shutdown_timeout = Some(*t);
+    }
+    if let Some(dir) = matches.get_one::<PathBuf>("scratch-dir") {
+        config.scratch_dir = Some(dir.clone());
+    }
+    if let Err(errors) = config.validate() {
+        for e in errors {
+            eprintln!("Invalid configuration: {}", e);
+        }
         std::process::exit(1);
-    });
+    }
+
+    let resources = Resources {
+        threads: config.threads,
+        memory: config.memory,
+        disk: config.disk,
+    };
 
-    let server = std::env::var("ZOIDBERG_SERVER")
-        .unwrap_or_else(|_| String::from(matches.value_of("server").unwrap()));
+    let secret = std::env::var("ZOIDBERG_TOKEN")
+        .or_else(|_| std::env::var("ZOIDBERG_SECRET"))
+        .unwrap_or_else(|_| {
+            eprintln!("Please set the $ZOIDBERG_TOKEN environment variable");
+            std::process::exit(1);
+        });
 
+    let mut api = ClientBuilder::new(config.server.as_ref().unwrap(), &secret)
+        .timeout(config.request_timeout())
+        .retries(config.request_retries);
+    if let Some(path) = &config.ca_cert {
+        api = api.ca_cert(&std::fs::read(path)?)?;
+    }
     let client = Arc::new(
-        Worker::new(&server, &secret, threads)
-            .await
-            .expect("Could not create client"),
+        Worker::new(
+            api.build()?,
+            resources,
+            config.kill_grace(),
+            config.fetch_wait,
+            config.fetch_batch,
+            // jobs may run in another working directory
+            &std::env::current_dir()?.join(config.scratch_dir()),
+        )
+        .await
+        .expect("Could not create client"),
     );
 
-    let pause = time::Duration::from_secs(1);
-    let long_pause = time::Duration::from_secs(40);
-    let heartbeat_pause = time::Duration::from_secs(30);
+    let pause = config.poll_interval();
+    let heartbeat_pause = config.heartbeat_interval();
+    // the first pause lasts about backoff_min and grows with every failure
+    let backoff = ExponentialBackoff::builder()
+        .retry_bounds(config.backoff_min(), config.backoff_max())
+        .build_with_max_retries(match config.retry_forever {
+            true => u32::MAX,
+            false => config.max_fetch_failures - 1,
+        });
 
     let (heartbeat_handle, abort_registration) = AbortHandle::new_pair();
     let c = Arc::clone(&client);
@@ -174,52 +679,203 @@ async fn main() -> Result<(), Box<dyn Error>> {
         async move {
             loop {
                 time::sleep(heartbeat_pause).await;
-                c.heartbeat().await;
+                if let Err(error) = c.heartbeat().await {
+                    log::error!("Could not send heartbeat: {}", error);
+                }
             }
         },
         abort_registration,
     ));
 
-    let mut fail_counter = 0;
-    loop {
-        let jobs = if let Ok(fetch) = client.fetch().await {
-            fail_counter = 0;
-            match fetch {
-                FetchResponse::Nop => {
-                    time::sleep(pause).await;
-                    continue;
-                }
-                FetchResponse::Terminate(m) => {
-                    println!("Terminate worker: {}", m);
-                    break;
-                }
-                FetchResponse::Jobs(jobs) => jobs,
+    let mut signals = Signals::new()?;
+    let mut stopped = false;
+    tokio::select! {
+        _ = fetch_jobs(&client, pause, &backoff) => {}
+        name = signals.recv() => {
+            log::info!("Received {}, no longer fetching jobs", name);
+            stopped = true;
+        }
+    }
+
+    // a second signal or the shutdown timeout kills the running jobs
+    if stopped && config.on_shutdown == OnShutdown::Kill {
+        client.interrupt().await;
+    } else {
+        let timeout = config.shutdown_timeout();
+        tokio::select! {
+            _ = client.idle() => {}
+            name = signals.recv() => {
+                log::info!("Received {}, killing running jobs", name);
+                client.interrupt().await;
             }
-        } else {
-            fail_counter += 1;
-            if fail_counter == 3 {
-                log::error!("failed to fetch three times, assume that server crashed and exit");
-                std::process::exit(1);
+            _ = time::sleep(timeout.unwrap_or_default()), if timeout.is_some() => {
+                log::info!("Jobs did not finish within the shutdown timeout, killing them");
+                client.interrupt().await;
             }
-            log::error!("failed to fetch new jobs");
-            time::sleep(long_pause).await;
-            continue;
+        }
+    }
+    heartbeat_handle.abort();
+    if let Err(error) = client.api.deregister(&client.id).await {
+        log::error!("Could not deregister worker: {}", error);
+    }
+    // only the directory of the worker is left, unless a job could not be
+    // cleaned up
+    let _ = std::fs::remove_dir(&client.scratch);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn worker(scratch: &Path) -> Worker {
+        let api = ClientBuilder::new("http://127.0.0.1:1", "token")
+            .build()
+            .unwrap();
+        let resources = Resources {
+            threads: 4,
+            memory: None,
+            disk: None,
+        };
+        Worker {
+            id: String::from("some_worker"),
+            api,
+            resources,
+            free: Mutex::new(resources),
+            finished: Notify::new(),
+            processes: Processes::default(),
+            kill_grace: Duration::from_millis(300),
+            fetch_wait: 0,
+            fetch_batch: 1,
+            scratch: scratch.to_path_buf(),
+        }
+    }
+
+    /// Path in the temporary directory that is unique for every test.
+    fn temp_path(test: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("zoidberg-{}-{}", test, std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn test_run_environment() {
+        let scratch = temp_path("environment");
+        let worker = worker(&scratch);
+        let job = Job {
+            id: 5,
+            argv: vec![String::from("/usr/bin/env")],
+            threads: 3,
+            clean_env: true,
+            env: [(String::from("MKL_NUM_THREADS"), String::from("1"))]
+                .into_iter()
+                .collect(),
+            array_id: Some(4),
+            array_index: Some(7),
+            ..Default::default()
         };
+        let outcome = run(&job, &worker).await.unwrap();
+        assert!(outcome.output.status.success());
+        let env = String::from_utf8(outcome.output.stdout).unwrap();
+        let vars: HashSet<&str> = env.lines().collect();
+        let expected = [
+            String::from("ZOIDBERG_JOB_ID=5"),
+            String::from("ZOIDBERG_WORKER_ID=some_worker"),
+            String::from("ZOIDBERG_SERVER=http://127.0.0.1:1"),
+            String::from("ZOIDBERG_THREADS=3"),
+            format!("ZOIDBERG_SCRATCH={}", scratch.join("job-5").display()),
+            String::from("ZOIDBERG_ARRAY_ID=4"),
+            String::from("ZOIDBERG_ARRAY_INDEX=7"),
+            String::from("OMP_NUM_THREADS=3"),
+            // variables of the job take precedence
+            String::from("MKL_NUM_THREADS=1"),
+        ];
+        for var in expected.iter() {
+            assert!(vars.contains(var.as_str()), "{} missing in {}", var, env);
+        }
+        assert!(!env.contains("PATH="));
+    }
 
-        for job in jobs {
-            let status = match run(&job).await {
-                Ok(()) => Status::Completed,
-                Err(..) => Status::Failed,
-            };
-            let update = &[Job {
-                status,
-                ..job.clone()
-            }];
-            if let Err(error) = client.update(update).await {
-                log::info!("Could not update job: {}", error);
+    #[tokio::test]
+    async fn test_run_walltime() {
+        let worker = worker(&temp_path("walltime"));
+        let job = |id, cmd: &str| Job {
+            id,
+            cmd: cmd.to_string(),
+            walltime: Some(1),
+            ..Default::default()
+        };
+        let sleep = job(1, "sleep 5");
+        // ignored signals stay ignored in the children
+        let stubborn = job(2, "trap '' TERM; sleep 5");
+        let started = time::Instant::now();
+        let (terminated, killed) = tokio::join!(run(&sleep, &worker), run(&stubborn, &worker));
+        let (terminated, killed) = (terminated.unwrap(), killed.unwrap());
+        assert!(started.elapsed() < Duration::from_secs(3));
+        assert!(terminated.timed_out && killed.timed_out);
+        assert_eq!(terminated.output.status.signal(), Some(libc::SIGTERM));
+        assert_eq!(killed.output.status.signal(), Some(libc::SIGKILL));
+        assert!(worker.processes.groups.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_interrupt() {
+        let worker = worker(&temp_path("interrupt"));
+        let job = Job {
+            id: 1,
+            cmd: String::from("sleep 5"),
+            ..Default::default()
+        };
+        let running = run(&job, &worker);
+        tokio::pin!(running);
+        while worker.processes.groups.lock().unwrap().is_empty() {
+            tokio::select! {
+                _ = &mut running => panic!("job ended before it was interrupted"),
+                _ = time::sleep(Duration::from_millis(10)) => {}
             }
         }
+        worker.processes.interrupt(libc::SIGTERM);
+        let outcome = running.await.unwrap();
+        assert!(!outcome.timed_out);
+        assert_eq!(outcome.output.status.signal(), Some(libc::SIGTERM));
+        assert!(worker.processes.was_interrupted(1));
+        assert!(!worker.processes.was_interrupted(2));
+    }
+
+    #[tokio::test]
+    async fn test_process_scratch() {
+        let scratch = temp_path("scratch");
+        let worker = Arc::new(worker(&scratch));
+        let marker = temp_path("scratch-marker");
+        let job = Job {
+            id: 3,
+            cmd: format!(
+                "touch $ZOIDBERG_SCRATCH/file && echo $ZOIDBERG_SCRATCH > {}",
+                marker.display()
+            ),
+            threads: 2,
+            ..Default::default()
+        };
+        worker.free.lock().unwrap().take(&job);
+        process(Arc::clone(&worker), job).await;
+        let used = std::fs::read_to_string(&marker).unwrap();
+        assert_eq!(used.trim(), scratch.join("job-3").display().to_string());
+        assert!(!scratch.join("job-3").exists());
+        assert_eq!(worker.free.lock().unwrap().threads, 4);
+        std::fs::remove_file(&marker).unwrap();
+        std::fs::remove_dir(&scratch).unwrap();
+    }
+
+    #[test]
+    fn test_check() {
+        let job = Job {
+            workdir: Some(std::env::temp_dir().display().to_string()),
+            ..Default::default()
+        };
+        assert!(check(&job).is_none());
+        let job = Job {
+            workdir: Some(String::from("/does/not/exist")),
+            ..Default::default()
+        };
+        assert!(check(&job).unwrap().contains("/does/not/exist"));
+        assert!(check(&Job::default()).is_none());
     }
-    heartbeat_handle.abort();
-    Ok(())
 }
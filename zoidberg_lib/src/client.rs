@@ -0,0 +1,450 @@
+//! Typed async client for the HTTP API of the server.
+
+use std::fmt;
+use std::time::Duration;
+
+use reqwest::{header, Certificate, Response, StatusCode};
+use reqwest_middleware::ClientWithMiddleware;
+use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
+use serde::de::DeserializeOwned;
+
+use crate::types::{
+    ArrayStatus, BatchStatusRequest, BatchStatusResponse, CancelRequest, DeregisterRequest,
+    FetchRequest, FetchResponse, Heartbeat, HeartbeatResponse, Job, JobArray, JobFilter, JobState,
+    Log, RegisterResponse, StatusRequest, TokenInfo, TokenRequest, TokenResponse, Update, Worker,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    /// The token is unknown (401) or lacks the required role (403).
+    Auth(StatusCode, String),
+    /// The server answered with any other error status.
+    Http(StatusCode, String),
+    /// The request could not be sent or the response not be received.
+    Transport(String),
+    /// The response is not what the endpoint is supposed to return.
+    Decode(serde_json::Error),
+    /// The client could not be set up, e.g. because of a broken certificate.
+    Config(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Auth(status, body) => write!(f, "not authorized ({}): {}", status, body),
+            Error::Http(status, body) => write!(f, "server answered {}: {}", status, body),
+            Error::Transport(e) => write!(f, "request failed: {}", e),
+            Error::Decode(e) => write!(f, "invalid response: {}", e),
+            Error::Config(e) => write!(f, "invalid client configuration: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Transport(e.to_string())
+    }
+}
+
+impl From<reqwest_middleware::Error> for Error {
+    fn from(e: reqwest_middleware::Error) -> Self {
+        Error::Transport(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Decode(e)
+    }
+}
+
+/// Splits a PEM bundle into its certificates, `Certificate::from_pem` only
+/// reads the first one.
+fn split_pem(pem: &str) -> Result<Vec<&str>, Error> {
+    const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+    const END: &str = "-----END CERTIFICATE-----";
+    let mut certs = Vec::new();
+    let mut rest = pem;
+    while let Some(start) = rest.find(BEGIN) {
+        let end = rest[start..]
+            .find(END)
+            .ok_or_else(|| Error::Config(String::from("unterminated certificate in PEM")))?
+            + start
+            + END.len();
+        certs.push(&rest[start..end]);
+        rest = &rest[end..];
+    }
+    match certs.is_empty() {
+        true => Err(Error::Config(String::from("no certificate found in PEM"))),
+        false => Ok(certs),
+    }
+}
+
+/// Collects the settings of a `Client`.
+pub struct ClientBuilder {
+    server: String,
+    token: String,
+    ca_certs: Vec<Certificate>,
+    timeout: Duration,
+    retries: u32,
+}
+
+impl ClientBuilder {
+    /// Client for the server at `server`, e.g. `http://localhost:8080`,
+    /// that authenticates with `token`.
+    pub fn new(server: &str, token: &str) -> Self {
+        ClientBuilder {
+            server: server.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+            ca_certs: Vec::new(),
+            timeout: Duration::from_secs(15),
+            retries: 0,
+        }
+    }
+
+    /// Additional PEM encoded CA certificates to verify the server with, a
+    /// single one or a bundle.
+    pub fn ca_cert(mut self, pem: &[u8]) -> Result<Self, Error> {
+        let pem = std::str::from_utf8(pem)
+            .map_err(|e| Error::Config(format!("CA certificate is not PEM: {}", e)))?;
+        for cert in split_pem(pem)? {
+            self.ca_certs.push(
+                Certificate::from_pem(cert.as_bytes()).map_err(|e| Error::Config(e.to_string()))?,
+            );
+        }
+        Ok(self)
+    }
+
+    /// Timeout of a single request.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Retries of requests that failed with a connection or server error.
+    ///
+    /// Requests that must not be repeated, fetching and submitting jobs and
+    /// reporting their status, are never retried.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn build(self) -> Result<Client, Error> {
+        let mut headers = header::HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            header::HeaderValue::from_str(&format!("Bearer {}", self.token))
+                .map_err(|e| Error::Config(format!("invalid token: {}", e)))?,
+        );
+        let mut builder = reqwest::ClientBuilder::new()
+            .timeout(self.timeout)
+            .default_headers(headers);
+        for cert in self.ca_certs {
+            builder = builder.add_root_certificate(cert);
+        }
+        let once = builder.build().map_err(|e| Error::Config(e.to_string()))?;
+        let retry_policy = ExponentialBackoff::builder().build_with_max_retries(self.retries);
+        let http = reqwest_middleware::ClientBuilder::new(once.clone())
+            .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+            .build();
+        Ok(Client {
+            server: self.server,
+            timeout: self.timeout,
+            http,
+            once,
+        })
+    }
+}
+
+/// Client for every endpoint of the server.
+#[derive(Clone, Debug)]
+pub struct Client {
+    server: String,
+    timeout: Duration,
+    /// Retries requests that failed with a connection or server error.
+    http: ClientWithMiddleware,
+    /// Used for requests that must not be repeated: if only the response
+    /// got lost, fetched jobs would never run, submitted jobs would be
+    /// submitted twice, a repeated failure could fail a retried job and a
+    /// repeated registration would leave a worker behind that never sends
+    /// a heartbeat.
+    once: reqwest::Client,
+}
+
+async fn read_text(res: Response) -> Result<String, Error> {
+    let status = res.status();
+    let body = res.text().await?;
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(Error::Auth(status, body)),
+        s if !s.is_success() => Err(Error::Http(status, body)),
+        _ => Ok(body),
+    }
+}
+
+async fn read<T: DeserializeOwned>(res: Response) -> Result<T, Error> {
+    Ok(serde_json::from_str(&read_text(res).await?)?)
+}
+
+impl Client {
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.server, path)
+    }
+
+    /// Address of the server, without trailing slash.
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    /// Registers a new worker and returns its ID.
+    pub async fn register(&self) -> Result<String, Error> {
+        let res = self.once.get(self.url("/register")).send().await?;
+        Ok(read::<RegisterResponse>(res).await?.id)
+    }
+
+    /// Asks for a job, waiting up to `request.wait` seconds for one.
+    pub async fn fetch(&self, request: &FetchRequest) -> Result<FetchResponse, Error> {
+        let wait = Duration::from_secs(request.wait.unwrap_or(0));
+        let res = self
+            .once
+            .post(self.url("/fetch"))
+            .timeout(self.timeout + wait)
+            .json(request)
+            .send()
+            .await?;
+        read(res).await
+    }
+
+    /// Reports the status of jobs, returns the message of the server.
+    pub async fn update(&self, updates: &[Update]) -> Result<String, Error> {
+        let res = self
+            .once
+            .post(self.url("/update"))
+            .json(updates)
+            .send()
+            .await?;
+        read_text(res).await
+    }
+
+    pub async fn heartbeat(&self, worker: &str) -> Result<HeartbeatResponse, Error> {
+        let res = self
+            .http
+            .post(self.url("/heartbeat"))
+            .json(&Heartbeat {
+                id: worker.to_string(),
+            })
+            .send()
+            .await?;
+        read(res).await
+    }
+
+    /// Removes the worker, its unfinished jobs are failed or retried.
+    pub async fn deregister(&self, worker: &str) -> Result<(), Error> {
+        let res = self
+            .http
+            .post(self.url("/deregister"))
+            .json(&DeregisterRequest {
+                id: worker.to_string(),
+            })
+            .send()
+            .await?;
+        read_text(res).await.map(|_| ())
+    }
+
+    pub async fn upload_log(&self, log: &Log) -> Result<(), Error> {
+        let res = self.http.post(self.url("/log")).json(log).send().await?;
+        read_text(res).await.map(|_| ())
+    }
+
+    /// Submits jobs and returns them with their assigned IDs.
+    pub async fn submit(&self, jobs: &[Job]) -> Result<Vec<Job>, Error> {
+        let res = self
+            .once
+            .post(self.url("/submit"))
+            .json(jobs)
+            .send()
+            .await?;
+        read(res).await
+    }
+
+    /// Submits a job array and returns its tasks, the array is named after
+    /// the ID of the first one.
+    pub async fn submit_array(&self, array: &JobArray) -> Result<Vec<Job>, Error> {
+        let res = self
+            .once
+            .post(self.url("/arrays"))
+            .json(array)
+            .send()
+            .await?;
+        read(res).await
+    }
+
+    /// Number of tasks of a job array in every status.
+    pub async fn array(&self, id: i32) -> Result<ArrayStatus, Error> {
+        let res = self
+            .http
+            .get(self.url(&format!("/arrays/{}", id)))
+            .send()
+            .await?;
+        read(res).await
+    }
+
+    /// Cancels the unfinished tasks of a job array and returns them.
+    pub async fn cancel_array(&self, id: i32) -> Result<Vec<Job>, Error> {
+        let res = self
+            .http
+            .post(self.url(&format!("/arrays/{}/cancel", id)))
+            .send()
+            .await?;
+        read(res).await
+    }
+
+    pub async fn status(&self, ids: &[i32]) -> Result<Vec<Job>, Error> {
+        let request: Vec<StatusRequest> = ids.iter().map(|&id| StatusRequest { id }).collect();
+        let res = self
+            .http
+            .post(self.url("/status"))
+            .json(&request)
+            .send()
+            .await?;
+        read(res).await
+    }
+
+    /// States of the jobs for workflow managers, in the requested order.
+    pub async fn batch_status(&self, ids: &[i32]) -> Result<Vec<JobState>, Error> {
+        let res = self
+            .http
+            .post(self.url("/v1/status"))
+            .json(&BatchStatusRequest { ids: ids.to_vec() })
+            .send()
+            .await?;
+        Ok(read::<BatchStatusResponse>(res).await?.jobs)
+    }
+
+    pub async fn jobs(&self, filter: &JobFilter) -> Result<Vec<Job>, Error> {
+        let res = self
+            .http
+            .get(self.url("/jobs"))
+            .query(filter)
+            .send()
+            .await?;
+        read(res).await
+    }
+
+    pub async fn log(&self, id: i32) -> Result<Log, Error> {
+        let res = self
+            .http
+            .get(self.url(&format!("/log/{}", id)))
+            .send()
+            .await?;
+        read(res).await
+    }
+
+    /// Cancels jobs and returns the ones that were not finished yet.
+    pub async fn cancel(&self, ids: &[i32]) -> Result<Vec<Job>, Error> {
+        let request: Vec<CancelRequest> = ids.iter().map(|&id| CancelRequest { id }).collect();
+        let res = self
+            .http
+            .post(self.url("/cancel"))
+            .json(&request)
+            .send()
+            .await?;
+        read(res).await
+    }
+
+    pub async fn workers(&self) -> Result<Vec<Worker>, Error> {
+        let res = self.http.get(self.url("/workers")).send().await?;
+        read(res).await
+    }
+
+    /// Stops giving jobs to a worker and tells it to terminate.
+    pub async fn drain(&self, worker: &str) -> Result<Worker, Error> {
+        let res = self
+            .http
+            .post(self.url(&format!("/workers/{}/drain", worker)))
+            .send()
+            .await?;
+        read(res).await
+    }
+
+    pub async fn create_token(&self, request: &TokenRequest) -> Result<TokenResponse, Error> {
+        let res = self
+            .once
+            .post(self.url("/tokens"))
+            .json(request)
+            .send()
+            .await?;
+        read(res).await
+    }
+
+    pub async fn tokens(&self) -> Result<Vec<TokenInfo>, Error> {
+        let res = self.http.get(self.url("/tokens")).send().await?;
+        read(res).await
+    }
+
+    pub async fn revoke_token(&self, name: &str) -> Result<(), Error> {
+        let res = self
+            .http
+            .delete(self.url(&format!("/tokens/{}", name)))
+            .send()
+            .await?;
+        read_text(res).await.map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_pem() {
+        let pem = "# first\n-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n\
+                   -----BEGIN CERTIFICATE-----\nBBBB\n-----END CERTIFICATE-----\n";
+        let certs = split_pem(pem).unwrap();
+        assert_eq!(certs.len(), 2);
+        assert!(certs[0].contains("AAAA") && !certs[0].contains("BBBB"));
+        assert!(certs[1].starts_with("-----BEGIN") && certs[1].ends_with("-----"));
+
+        assert!(split_pem("no certificate").is_err());
+        assert!(split_pem("-----BEGIN CERTIFICATE-----\nAAAA\n").is_err());
+        assert!(ClientBuilder::new("http://localhost", "token")
+            .ca_cert(b"no certificate")
+            .is_err());
+    }
+
+    fn response(status: u16, body: &str) -> Response {
+        http::Response::builder()
+            .status(status)
+            .body(body.to_string())
+            .unwrap()
+            .into()
+    }
+
+    #[tokio::test]
+    async fn test_read() {
+        assert_eq!(read_text(response(200, "ok")).await.unwrap(), "ok");
+        assert!(matches!(
+            read_text(response(401, "no token")).await,
+            Err(Error::Auth(StatusCode::UNAUTHORIZED, body)) if body == "no token"
+        ));
+        assert!(matches!(
+            read_text(response(403, "")).await,
+            Err(Error::Auth(StatusCode::FORBIDDEN, _))
+        ));
+        assert!(matches!(
+            read_text(response(404, "No job array 5")).await,
+            Err(Error::Http(StatusCode::NOT_FOUND, _))
+        ));
+
+        let worker: Worker = read(response(200, r#"{"id": "some_worker"}"#))
+            .await
+            .unwrap();
+        assert_eq!(worker.id, "some_worker");
+        assert!(matches!(
+            read::<Worker>(response(200, "not json")).await,
+            Err(Error::Decode(_))
+        ));
+    }
+}
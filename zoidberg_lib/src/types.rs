@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fmt;
 
 use serde::{Deserialize, Serialize};
@@ -7,47 +8,436 @@ pub struct Update {
     pub worker: String,
     pub job: i32,
     pub status: Status,
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    #[serde(default)]
+    pub signal: Option<i32>,
+    #[serde(default)]
+    pub reason: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub enum Status {
+    #[default]
     Submitted,
     Running(String),
     Completed,
     Failed,
+    Cancelled,
+    /// Killed because it exceeded its walltime.
+    TimedOut,
 }
 
 impl Status {
-    fn default() -> Self {
-        Status::Submitted
+    /// Whether the job reached a state that it will not leave anymore.
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            Status::Completed | Status::Failed | Status::Cancelled | Status::TimedOut
+        )
+    }
+
+    /// Name of the status without the worker, e.g. `running`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Status::Submitted => "submitted",
+            Status::Running(_) => "running",
+            Status::Completed => "completed",
+            Status::Failed => "failed",
+            Status::Cancelled => "cancelled",
+            Status::TimedOut => "timed-out",
+        }
     }
 }
 
 impl fmt::Display for Status {
-    fn fmt(self: &Self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Status::Submitted => write!(f, "submitted"),
             Status::Running(w) => write!(f, "running on worker {}", w),
             Status::Completed => write!(f, "completed"),
             Status::Failed => write!(f, "failed"),
+            Status::Cancelled => write!(f, "cancelled"),
+            Status::TimedOut => write!(f, "timed out"),
         }
     }
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Log {
+    pub worker: String,
+    pub job: i32,
+    #[serde(default)]
+    pub stdout: String,
+    #[serde(default)]
+    pub stderr: String,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct StatusRequest {
     pub id: i32,
 }
 
+/// Condition on the jobs listed in `Job::after`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dependency {
+    /// Start after all of them completed, fail if one of them failed.
+    #[default]
+    AfterOk,
+    /// Start after all of them finished, regardless of their status.
+    AfterAny,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CancelRequest {
+    pub id: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct Job {
     #[serde(default)]
     pub id: i32,
+    /// Shell command, only shown for information if `argv` is set.
+    #[serde(default)]
     pub cmd: String,
+    /// Program and arguments that are run without a shell.
+    #[serde(default)]
+    pub argv: Vec<String>,
+    /// Interpreter that runs `cmd` with `-c`, bash if not set.
+    #[serde(default)]
+    pub shell: Option<String>,
+    /// Absolute path of the directory the job runs in on the worker.
+    #[serde(default)]
+    pub workdir: Option<String>,
+    /// Variables that are added to the environment of the job.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Start from an empty environment instead of the one of the worker.
+    #[serde(default)]
+    pub clean_env: bool,
+    /// Seconds the job may run before it is killed, unlimited if not set.
+    #[serde(default)]
+    pub walltime: Option<i64>,
     #[serde(default = "Status::default")]
     pub status: Status,
     #[serde(default)]
     pub threads: i32,
+    /// Required memory in MB.
+    #[serde(default)]
+    pub memory: i64,
+    /// Required disk space in MB.
+    #[serde(default)]
+    pub disk: i64,
+    /// IDs of jobs that have to finish before this job starts.
+    ///
+    /// Within one submission, jobs can also refer to jobs listed before
+    /// them by the `id` they were submitted with.
+    #[serde(default)]
+    pub after: Vec<i32>,
+    #[serde(default)]
+    pub dependency: Dependency,
+    /// Jobs with higher priority are handed out first.
+    #[serde(default)]
+    pub priority: i32,
+    /// User the job is accounted to.
+    #[serde(default)]
+    pub owner: String,
+    #[serde(default)]
+    pub project: Option<String>,
+    /// Job array the job is a task of, named after the ID of its first task.
+    #[serde(default)]
+    pub array_id: Option<i32>,
+    /// Index of the task within its job array.
+    #[serde(default)]
+    pub array_index: Option<i64>,
+    /// Unix timestamps of submission, start and end of the job.
+    #[serde(default)]
+    pub submitted_at: Option<i64>,
+    #[serde(default)]
+    pub started_at: Option<i64>,
+    #[serde(default)]
+    pub finished_at: Option<i64>,
+    /// Worker that ran the job.
+    #[serde(default)]
+    pub worker: Option<String>,
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    /// Signal that terminated the process.
+    #[serde(default)]
+    pub signal: Option<i32>,
+    /// Why the job failed.
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Number of retries after failures, overrides the server default.
+    #[serde(default)]
+    pub max_retries: Option<i32>,
+    /// Current attempt, starting at 1.
+    #[serde(default)]
+    pub attempt: i32,
+    /// Earlier attempts of this job.
+    #[serde(default)]
+    pub history: Vec<Attempt>,
+}
+
+/// Record of a finished attempt of a job that was retried afterwards.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Attempt {
+    pub attempt: i32,
+    pub status: Status,
+    #[serde(default)]
+    pub worker: Option<String>,
+    #[serde(default)]
+    pub started_at: Option<i64>,
+    #[serde(default)]
+    pub finished_at: Option<i64>,
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    #[serde(default)]
+    pub signal: Option<i32>,
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// The attempt ended because the worker stopped sending heartbeats or
+    /// shut down, the job itself did not fail.
+    #[serde(default)]
+    pub lost_worker: bool,
+}
+
+impl Job {
+    /// Checks what can be checked before the job reaches a worker.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.cmd.trim().is_empty() && self.argv.is_empty() {
+            return Err(String::from("job has neither cmd nor argv"));
+        }
+        if self.argv.first().is_some_and(|p| p.is_empty()) {
+            return Err(String::from("argv starts with an empty program"));
+        }
+        if self.threads < 0 {
+            return Err(String::from("threads must not be negative"));
+        }
+        if self.memory < 0 {
+            return Err(String::from("memory must not be negative"));
+        }
+        if self.disk < 0 {
+            return Err(String::from("disk must not be negative"));
+        }
+        if self.walltime.is_some_and(|w| w <= 0) {
+            return Err(String::from("walltime must be positive"));
+        }
+        if let Some(dir) = &self.workdir {
+            if !dir.starts_with('/') {
+                return Err(format!("working directory {} is not absolute", dir));
+            }
+        }
+        if let Some(key) = self
+            .env
+            .keys()
+            .find(|k| k.is_empty() || k.contains('=') || k.contains('\0'))
+        {
+            return Err(format!("invalid environment variable name {:?}", key));
+        }
+        Ok(())
+    }
+
+    /// Runtime in seconds, as far as the job has been running.
+    pub fn runtime(&self, now: i64) -> Option<i64> {
+        self.started_at
+            .map(|start| self.finished_at.unwrap_or(now) - start)
+    }
+}
+
+/// Most tasks a job array may have.
+pub const MAX_ARRAY_TASKS: usize = 100_000;
+
+fn one() -> i64 {
+    1
+}
+
+/// Parameters of the tasks of a job array.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum ArrayParams {
+    /// One task per index from `start` to `end`, both included.
+    Range {
+        start: i64,
+        end: i64,
+        #[serde(default = "one")]
+        step: i64,
+    },
+    /// One task per value, indexed from 0.
+    Values(Vec<String>),
+}
+
+/// Request of `POST /arrays`, a job that is run once per parameter.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct JobArray {
+    /// Template of the tasks, `{}` in `cmd` and `argv` is replaced by the
+    /// index or value of the task.
+    pub job: Job,
+    pub params: ArrayParams,
+}
+
+impl JobArray {
+    /// Expands the template into one job per parameter.
+    pub fn tasks(&self) -> Result<Vec<Job>, String> {
+        let params: Vec<(i64, String)> = match &self.params {
+            ArrayParams::Range { start, end, step } => {
+                if *step <= 0 {
+                    return Err(String::from("step of the index range must be positive"));
+                }
+                if end < start {
+                    return Err(format!("index range {}-{} is empty", start, end));
+                }
+                let count = (*end as i128 - *start as i128) / *step as i128 + 1;
+                if count > MAX_ARRAY_TASKS as i128 {
+                    return Err(format!(
+                        "job array has {} tasks, at most {} are allowed",
+                        count, MAX_ARRAY_TASKS
+                    ));
+                }
+                // stays within start..=end, but a step past the first one
+                // can exceed the range of i64
+                (0..count)
+                    .map(|i| (*start as i128 + i * *step as i128) as i64)
+                    .map(|index| (index, index.to_string()))
+                    .collect()
+            }
+            ArrayParams::Values(values) => {
+                if values.is_empty() {
+                    return Err(String::from("job array has no values"));
+                }
+                if values.len() > MAX_ARRAY_TASKS {
+                    return Err(format!(
+                        "job array has {} tasks, at most {} are allowed",
+                        values.len(),
+                        MAX_ARRAY_TASKS
+                    ));
+                }
+                values
+                    .iter()
+                    .cloned()
+                    .enumerate()
+                    .map(|(i, v)| (i as i64, v))
+                    .collect()
+            }
+        };
+        Ok(params
+            .into_iter()
+            .map(|(index, value)| Job {
+                id: 0,
+                cmd: self.job.cmd.replace("{}", &value),
+                argv: self
+                    .job
+                    .argv
+                    .iter()
+                    .map(|a| a.replace("{}", &value))
+                    .collect(),
+                array_index: Some(index),
+                ..self.job.clone()
+            })
+            .collect())
+    }
+}
+
+/// Number of tasks of a job array in every status.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ArrayStatus {
+    pub id: i32,
+    pub tasks: usize,
+    /// Queued or waiting for their dependencies.
+    pub submitted: usize,
+    pub running: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+    pub timed_out: usize,
+}
+
+impl ArrayStatus {
+    /// Counts a task of the array.
+    pub fn add(&mut self, job: &Job) {
+        self.tasks += 1;
+        match job.status {
+            Status::Submitted => self.submitted += 1,
+            Status::Running(_) => self.running += 1,
+            Status::Completed => self.completed += 1,
+            Status::Failed => self.failed += 1,
+            Status::Cancelled => self.cancelled += 1,
+            Status::TimedOut => self.timed_out += 1,
+        }
+    }
+}
+
+/// Query of `GET /jobs`, fields that are not set match every job.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct JobFilter {
+    /// Status name as returned by `Status::name`.
+    #[serde(default)]
+    pub status: Option<String>,
+    #[serde(default)]
+    pub owner: Option<String>,
+}
+
+impl JobFilter {
+    pub fn matches(&self, job: &Job) -> bool {
+        self.status.as_ref().is_none_or(|s| s == job.status.name())
+            && self.owner.as_ref().is_none_or(|o| o == &job.owner)
+    }
+}
+
+/// State of a job as seen by workflow managers like Snakemake.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WorkflowState {
+    /// Queued or running, it may still succeed.
+    Running,
+    Success,
+    /// Failed, cancelled or unknown to the server.
+    Failed,
+}
+
+impl From<&Status> for WorkflowState {
+    fn from(status: &Status) -> Self {
+        match status {
+            Status::Submitted | Status::Running(_) => WorkflowState::Running,
+            Status::Completed => WorkflowState::Success,
+            Status::Failed | Status::Cancelled | Status::TimedOut => WorkflowState::Failed,
+        }
+    }
+}
+
+impl fmt::Display for WorkflowState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorkflowState::Running => write!(f, "running"),
+            WorkflowState::Success => write!(f, "success"),
+            WorkflowState::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// Request of `POST /v1/status`.
+///
+/// The `/v1` types only ever gain optional fields, so that workflow
+/// managers keep working with newer servers.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct BatchStatusRequest {
+    pub ids: Vec<i32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct JobState {
+    pub id: i32,
+    pub state: WorkflowState,
+    /// Name of the job status as returned by `Status::name`, `unknown` if
+    /// the server does not know the job.
+    pub status: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// Response of `POST /v1/status`, with the jobs in the requested order.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct BatchStatusResponse {
+    pub jobs: Vec<JobState>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -60,11 +450,56 @@ pub struct RegisterResponse {
     pub id: String,
 }
 
-#[derive(Serialize, Deserialize)]
+/// Resources of a worker that are available for jobs.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct Resources {
+    #[serde(default)]
+    pub threads: i32,
+    /// Memory in MB, unlimited if not set.
+    #[serde(default)]
+    pub memory: Option<i64>,
+    /// Disk space in MB, unlimited if not set.
+    #[serde(default)]
+    pub disk: Option<i64>,
+}
+
+impl Resources {
+    /// Whether the job fits into the resources.
+    pub fn fits(&self, job: &Job) -> bool {
+        job.threads.max(1) <= self.threads
+            && self.memory.is_none_or(|m| job.memory <= m)
+            && self.disk.is_none_or(|d| job.disk <= d)
+    }
+
+    /// Removes the resources of a job, jobs that do not request threads
+    /// still occupy one.
+    pub fn take(&mut self, job: &Job) {
+        self.threads -= job.threads.max(1);
+        self.memory = self.memory.map(|m| m - job.memory);
+        self.disk = self.disk.map(|d| d - job.disk);
+    }
+
+    /// Gives back the resources of a finished job.
+    pub fn release(&mut self, job: &Job) {
+        self.threads += job.threads.max(1);
+        self.memory = self.memory.map(|m| m + job.memory);
+        self.disk = self.disk.map(|d| d + job.disk);
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct FetchRequest {
     pub worker_id: String,
+    /// Free resources of the worker.
+    #[serde(flatten)]
+    pub free: Resources,
+    /// Seconds the server may hold the request until a fitting job is
+    /// queued, it answers at once if not set.
     #[serde(default)]
-    pub threads: i32,
+    pub wait: Option<u64>,
+    /// Most jobs handed out at once, a single one if not set.
+    #[serde(default)]
+    pub max_jobs: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -79,12 +514,60 @@ pub struct Submit {
     pub cmd: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct Worker {
     #[serde(default)]
     pub id: String,
     #[serde(default)]
     pub last_heartbeat: Option<i64>,
+    /// The worker gets no new jobs and is told to terminate once it asks.
+    #[serde(default)]
+    pub draining: bool,
+}
+
+/// Request of a worker that shuts down.
+#[derive(Serialize, Deserialize)]
+pub struct DeregisterRequest {
+    pub id: String,
+}
+
+/// What a token is allowed to do, admins may do everything.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Worker,
+    Submitter,
+    Admin,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::Worker => write!(f, "worker"),
+            Role::Submitter => write!(f, "submitter"),
+            Role::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TokenRequest {
+    pub name: String,
+    pub role: Role,
+}
+
+/// Newly created token, the secret is only handed out once.
+#[derive(Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub name: String,
+    pub role: Role,
+    pub token: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TokenInfo {
+    pub name: String,
+    pub role: Role,
+    pub created_at: i64,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -92,3 +575,10 @@ pub struct Heartbeat {
     #[serde(default)]
     pub id: String,
 }
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct HeartbeatResponse {
+    /// Jobs running on the worker that have to be killed.
+    #[serde(default)]
+    pub cancel: Vec<i32>,
+}
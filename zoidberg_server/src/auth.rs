@@ -1,24 +1,99 @@
-use actix_web::{dev, error::ErrorBadRequest, Error, FromRequest, HttpRequest, Result};
+use actix_web::{
+    dev,
+    error::{ErrorForbidden, ErrorUnauthorized},
+    web, Error, FromRequest, HttpRequest, Result,
+};
 use futures::future::{err, ok, Ready};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use zoidberg_lib::types::Role;
 
-pub struct Authorization {}
+use crate::State;
+
+/// Token as it is kept by the server, only the hash of the secret is stored.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Token {
+    pub name: String,
+    pub role: Role,
+    pub hash: String,
+    pub created_at: i64,
+}
+
+pub fn hash(secret: &str) -> String {
+    format!("{:x}", Sha256::digest(secret.as_bytes()))
+}
+
+pub fn generate() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// Token of a `Bearer` authorization header, or the password of a `Basic`
+/// one, which is how browsers send it.
+fn credentials(head: &str) -> Option<String> {
+    if let Some(bearer) = head.strip_prefix("Bearer ") {
+        return Some(bearer.to_string());
+    }
+    let basic = base64::decode(head.strip_prefix("Basic ")?.trim()).ok()?;
+    let basic = String::from_utf8(basic).ok()?;
+    basic
+        .split_once(':')
+        .map(|(_, password)| password.to_string())
+}
+
+/// Name and role of the token that authorized a request.
+pub struct Authorization {
+    pub name: String,
+    pub role: Role,
+}
+
+impl Authorization {
+    /// Fails unless the token has the given role, admins may do everything.
+    pub fn require(&self, role: Role) -> Result<()> {
+        if self.role == role || self.role == Role::Admin {
+            Ok(())
+        } else {
+            Err(ErrorForbidden(format!(
+                "token {} is a {} token, this requires a {} token",
+                self.name, self.role, role
+            )))
+        }
+    }
+}
 
 impl FromRequest for Authorization {
     type Error = Error;
     type Future = Ready<Result<Self, Self::Error>>;
 
     fn from_request(req: &HttpRequest, _payload: &mut dev::Payload) -> Self::Future {
-        if let Some(head) = req.headers().get("cookie") {
-            if let Ok(cookie) = head.to_str() {
-                if let Some(secret) = req.app_data::<String>() {
-                    if secret == cookie {
-                        return ok(Authorization {});
-                    } else {
-                        return err(ErrorBadRequest("no auth"));
-                    }
-                }
+        let head = req
+            .headers()
+            .get("authorization")
+            .and_then(|head| head.to_str().ok());
+        let bearer = match head.and_then(credentials) {
+            Some(b) => b,
+            None => return err(ErrorUnauthorized("no auth")),
+        };
+        let bearer = bearer.trim();
+        // the secret the server was started with is the admin token
+        if let Some(secret) = req.app_data::<String>() {
+            if secret == bearer {
+                return ok(Authorization {
+                    name: String::from("admin"),
+                    role: Role::Admin,
+                });
+            }
+        }
+        if let Some(data) = req.app_data::<web::Data<State>>() {
+            let hash = hash(bearer);
+            let tokens = data.tokens.lock().unwrap();
+            if let Some(t) = tokens.iter().find(|t| t.hash == hash) {
+                return ok(Authorization {
+                    name: t.name.clone(),
+                    role: t.role,
+                });
             }
         }
-        err(ErrorBadRequest("no auth"))
+        err(ErrorUnauthorized("no auth"))
     }
 }
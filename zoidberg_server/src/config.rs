@@ -0,0 +1,315 @@
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::scheduler::Policy;
+
+/// Server configuration, read from a TOML file and overridden by
+/// `ZOIDBERG_*` environment variables and command line options.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Addresses to listen on, e.g. `0.0.0.0:8080`.
+    pub listen: Vec<String>,
+    pub tls: Option<Tls>,
+    pub auth: Auth,
+    pub storage: Storage,
+    pub scheduler: Scheduler,
+    pub retry: Retry,
+    pub limits: Limits,
+    pub timeouts: Timeouts,
+    pub retention: Retention,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Tls {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Auth {
+    /// Secret of the admin token.
+    pub secret: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Storage {
+    /// Journal file, the state is only kept in memory if not set.
+    pub journal: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Scheduler {
+    pub policy: Policy,
+    /// Seconds after which the usage of an owner counts half for fair-share.
+    pub half_life: u64,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Retry {
+    pub max_retries: i32,
+    /// Requeues of a job after its worker was lost, before it fails.
+    pub max_lost_worker_retries: i32,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// Walltime of jobs that do not request one, unlimited if not set.
+    pub default_walltime: Option<u64>,
+    /// Longest walltime a job may request.
+    pub max_walltime: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timeouts {
+    /// Seconds between checks for lost workers.
+    pub reap_interval: u64,
+    /// Seconds without heartbeat after which a worker is considered lost.
+    pub worker: u64,
+    /// Longest time in seconds a worker may wait in `/fetch` for a job,
+    /// 0 answers at once.
+    pub max_fetch_wait: u64,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Retention {
+    /// Seconds after which finished jobs and their logs are forgotten,
+    /// they are kept forever if not set.
+    pub finished_jobs: Option<u64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            listen: vec![String::from("0.0.0.0:8080")],
+            tls: None,
+            auth: Auth::default(),
+            storage: Storage::default(),
+            scheduler: Scheduler::default(),
+            retry: Retry::default(),
+            limits: Limits::default(),
+            timeouts: Timeouts::default(),
+            retention: Retention::default(),
+        }
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Scheduler {
+            policy: Policy::default(),
+            half_life: 3600,
+        }
+    }
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Retry {
+            max_retries: 0,
+            max_lost_worker_retries: 3,
+        }
+    }
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            reap_interval: 10,
+            worker: 60,
+            max_fetch_wait: 30,
+        }
+    }
+}
+
+fn parse<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {:?} for {}", value, name))
+}
+
+impl Config {
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        toml::from_str(&content).map_err(|e| format!("invalid config {}: {}", path.display(), e))
+    }
+
+    /// Overrides settings with the `ZOIDBERG_*` variables returned by `var`.
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), String> {
+        if let Some(v) = var("ZOIDBERG_LISTEN") {
+            self.listen = v.split(',').map(|s| s.trim().to_string()).collect();
+        }
+        match (var("ZOIDBERG_TLS_CERT"), var("ZOIDBERG_TLS_KEY")) {
+            (Some(cert), Some(key)) => {
+                self.tls = Some(Tls {
+                    cert: cert.into(),
+                    key: key.into(),
+                })
+            }
+            (None, None) => {}
+            _ => {
+                return Err(String::from(
+                    "ZOIDBERG_TLS_CERT and ZOIDBERG_TLS_KEY must be set together",
+                ))
+            }
+        }
+        if let Some(v) = var("ZOIDBERG_SECRET") {
+            self.auth.secret = Some(v);
+        }
+        if let Some(v) = var("ZOIDBERG_JOURNAL") {
+            self.storage.journal = Some(v.into());
+        }
+        if let Some(v) = var("ZOIDBERG_SCHEDULER") {
+            self.scheduler.policy = v.parse()?;
+        }
+        if let Some(v) = var("ZOIDBERG_HALF_LIFE") {
+            self.scheduler.half_life = parse("ZOIDBERG_HALF_LIFE", &v)?;
+        }
+        if let Some(v) = var("ZOIDBERG_MAX_RETRIES") {
+            self.retry.max_retries = parse("ZOIDBERG_MAX_RETRIES", &v)?;
+        }
+        if let Some(v) = var("ZOIDBERG_MAX_LOST_WORKER_RETRIES") {
+            self.retry.max_lost_worker_retries = parse("ZOIDBERG_MAX_LOST_WORKER_RETRIES", &v)?;
+        }
+        if let Some(v) = var("ZOIDBERG_DEFAULT_WALLTIME") {
+            self.limits.default_walltime = Some(parse("ZOIDBERG_DEFAULT_WALLTIME", &v)?);
+        }
+        if let Some(v) = var("ZOIDBERG_MAX_WALLTIME") {
+            self.limits.max_walltime = Some(parse("ZOIDBERG_MAX_WALLTIME", &v)?);
+        }
+        if let Some(v) = var("ZOIDBERG_REAP_INTERVAL") {
+            self.timeouts.reap_interval = parse("ZOIDBERG_REAP_INTERVAL", &v)?;
+        }
+        if let Some(v) = var("ZOIDBERG_WORKER_TIMEOUT") {
+            self.timeouts.worker = parse("ZOIDBERG_WORKER_TIMEOUT", &v)?;
+        }
+        if let Some(v) = var("ZOIDBERG_MAX_FETCH_WAIT") {
+            self.timeouts.max_fetch_wait = parse("ZOIDBERG_MAX_FETCH_WAIT", &v)?;
+        }
+        if let Some(v) = var("ZOIDBERG_RETENTION") {
+            self.retention.finished_jobs = Some(parse("ZOIDBERG_RETENTION", &v)?);
+        }
+        Ok(())
+    }
+
+    /// Checks the settings and returns all problems that were found.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        if self.auth.secret.as_deref().unwrap_or("").is_empty() {
+            errors.push(String::from(
+                "no secret set, set auth.secret or $ZOIDBERG_SECRET",
+            ));
+        }
+        if self.listen.is_empty() {
+            errors.push(String::from("no listen address set"));
+        }
+        for address in self.listen.iter() {
+            if address.parse::<SocketAddr>().is_err() {
+                errors.push(format!("invalid listen address {}", address));
+            }
+        }
+        if let Some(tls) = &self.tls {
+            for path in [&tls.cert, &tls.key] {
+                if !path.is_file() {
+                    errors.push(format!("TLS file {} does not exist", path.display()));
+                }
+            }
+        }
+        if self.scheduler.half_life == 0 {
+            errors.push(String::from("scheduler.half_life must be positive"));
+        }
+        if self.retry.max_retries < 0 || self.retry.max_lost_worker_retries < 0 {
+            errors.push(String::from("retries must not be negative"));
+        }
+        if self.limits.default_walltime == Some(0) || self.limits.max_walltime == Some(0) {
+            errors.push(String::from("walltime limits must be positive"));
+        }
+        if let (Some(default), Some(max)) = (self.limits.default_walltime, self.limits.max_walltime)
+        {
+            if default > max {
+                errors.push(String::from(
+                    "limits.default_walltime must not exceed limits.max_walltime",
+                ));
+            }
+        }
+        if self.timeouts.reap_interval == 0 {
+            errors.push(String::from("timeouts.reap_interval must be positive"));
+        }
+        if self.timeouts.worker <= self.timeouts.reap_interval {
+            errors.push(String::from(
+                "timeouts.worker must be longer than timeouts.reap_interval",
+            ));
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_example_config() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("config.example.toml");
+        let config = Config::from_file(&path).unwrap();
+        assert_eq!(config.scheduler.policy, Policy::FairShare);
+        assert_eq!(config.timeouts.worker, 60);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let mut config: Config = toml::from_str("listen = [\"127.0.0.1:9000\"]").unwrap();
+        let env: HashMap<&str, &str> = [
+            ("ZOIDBERG_SECRET", "secret"),
+            ("ZOIDBERG_SCHEDULER", "fifo"),
+            ("ZOIDBERG_WORKER_TIMEOUT", "120"),
+        ]
+        .into_iter()
+        .collect();
+        config
+            .apply_env(|k| env.get(k).map(|v| v.to_string()))
+            .unwrap();
+        assert_eq!(config.listen, vec!["127.0.0.1:9000"]);
+        assert_eq!(config.scheduler.policy, Policy::Fifo);
+        assert_eq!(config.timeouts.worker, 120);
+        assert!(config.validate().is_ok());
+
+        let err = config.apply_env(|k| match k {
+            "ZOIDBERG_MAX_RETRIES" => Some(String::from("many")),
+            _ => None,
+        });
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn test_validation() {
+        let config: Config = toml::from_str(
+            r#"
+            listen = ["localhost"]
+            [timeouts]
+            reap_interval = 0
+            "#,
+        )
+        .unwrap();
+        let errors = config.validate().unwrap_err();
+        assert_eq!(errors.len(), 3);
+
+        assert!(toml::from_str::<Config>("unknown = 1").is_err());
+    }
+}
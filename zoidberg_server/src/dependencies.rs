@@ -0,0 +1,104 @@
+use zoidberg_lib::types::{Dependency, Job, Status};
+
+use crate::store::Store;
+
+enum Readiness {
+    Ready,
+    Waiting,
+    /// The dependencies can never be satisfied, the job ends with this status.
+    Broken(Status, String),
+}
+
+fn readiness(job: &Job, store: &Store) -> Readiness {
+    let mut ready = true;
+    for parent in job.after.iter() {
+        match (store.get(*parent).map(|p| &p.status), job.dependency) {
+            (None, _) => {
+                return Readiness::Broken(Status::Failed, format!("unknown dependency {}", parent))
+            }
+            (Some(Status::Failed | Status::TimedOut), Dependency::AfterOk) => {
+                return Readiness::Broken(Status::Failed, format!("dependency {} failed", parent))
+            }
+            (Some(Status::Cancelled), Dependency::AfterOk) => {
+                return Readiness::Broken(
+                    Status::Cancelled,
+                    format!("dependency {} was cancelled", parent),
+                )
+            }
+            (Some(s), _) => ready &= s.is_final(),
+        }
+    }
+    match ready {
+        true => Readiness::Ready,
+        false => Readiness::Waiting,
+    }
+}
+
+/// Queues the waiting jobs whose dependencies are satisfied and ends the
+/// jobs whose dependencies cannot be satisfied anymore, which in turn
+/// cascades to their own dependents.
+///
+/// Only jobs that were submitted or whose dependencies finished since the
+/// last call are checked. Returns the IDs of the jobs that were ended.
+pub fn resolve(store: &mut Store, now: i64) -> Vec<i32> {
+    let mut ended = Vec::new();
+    while let Some(id) = store.take_pending() {
+        match readiness(store.get(id).unwrap(), store) {
+            Readiness::Ready => store.enqueue(id),
+            Readiness::Waiting => {}
+            Readiness::Broken(status, reason) => {
+                log::info!("Job {} ends as {}: {}", id, status, reason);
+                store.dequeue(id);
+                let mut job = store.get(id).unwrap().clone();
+                job.status = status;
+                job.reason = Some(reason);
+                job.finished_at = Some(now);
+                // queues the dependents of the job for the next iteration
+                store.replace(job);
+                ended.push(id);
+            }
+        }
+    }
+    ended
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(id: i32, status: Status, after: Vec<i32>, dependency: Dependency) -> Job {
+        Job {
+            id,
+            cmd: String::from("hi"),
+            status,
+            after,
+            dependency,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut store = Store::default();
+        for job in [
+            job(1, Status::Completed, vec![], Dependency::AfterOk),
+            job(2, Status::Failed, vec![], Dependency::AfterOk),
+            job(3, Status::Submitted, vec![1], Dependency::AfterOk),
+            job(4, Status::Submitted, vec![1, 2], Dependency::AfterAny),
+            job(5, Status::Submitted, vec![1, 2], Dependency::AfterOk),
+            job(6, Status::Submitted, vec![5], Dependency::AfterOk),
+            job(7, Status::Submitted, vec![3], Dependency::AfterOk),
+        ] {
+            store.insert(job);
+        }
+        let ended = resolve(&mut store, 0);
+        assert_eq!(ended, vec![5, 6]);
+        assert_eq!(store.queued_len(), 2);
+        assert_eq!(store.waiting(), vec![7]);
+        assert!(matches!(store.get(6).unwrap().status, Status::Failed));
+
+        // already queued jobs are not queued twice
+        resolve(&mut store, 0);
+        assert_eq!(store.queued_len(), 2);
+    }
+}
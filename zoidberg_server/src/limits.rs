@@ -0,0 +1,61 @@
+use zoidberg_lib::types::Job;
+
+/// Walltime limits that are applied to submitted jobs, in seconds.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Limits {
+    /// Walltime of jobs that do not request one, unlimited if not set.
+    pub default_walltime: Option<i64>,
+    /// Longest walltime a job may request.
+    pub max_walltime: Option<i64>,
+}
+
+impl Limits {
+    /// Gives the job the default walltime if it has none and rejects it if
+    /// it asks for more than the maximum.
+    pub fn apply(&self, job: &mut Job) -> Result<(), String> {
+        job.walltime = job.walltime.or(self.default_walltime).or(self.max_walltime);
+        match (job.walltime, self.max_walltime) {
+            (Some(walltime), Some(max)) if walltime > max => Err(format!(
+                "walltime of {} s exceeds the maximum of {} s",
+                walltime, max
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let limits = Limits {
+            default_walltime: Some(60),
+            max_walltime: Some(3600),
+        };
+        let mut job = Job::default();
+        limits.apply(&mut job).unwrap();
+        assert_eq!(job.walltime, Some(60));
+
+        job.walltime = Some(3600);
+        limits.apply(&mut job).unwrap();
+        assert_eq!(job.walltime, Some(3600));
+
+        job.walltime = Some(3601);
+        assert!(limits.apply(&mut job).is_err());
+
+        // without default the maximum applies
+        let limits = Limits {
+            default_walltime: None,
+            max_walltime: Some(3600),
+        };
+        let mut job = Job::default();
+        limits.apply(&mut job).unwrap();
+        assert_eq!(job.walltime, Some(3600));
+
+        let mut job = Job::default();
+        Limits::default().apply(&mut job).unwrap();
+        assert_eq!(job.walltime, None);
+    }
+}
@@ -1,131 +1,404 @@
 use actix_web::{
-    get, middleware::Logger, post, web, App, HttpResponse, HttpServer, Responder, Result,
+    delete,
+    error::{ErrorBadRequest, ErrorInternalServerError, ErrorNotFound},
+    get,
+    middleware::Logger,
+    post, web, App, HttpResponse, HttpServer, Responder, Result,
 };
 use chrono::Utc;
+use clap::arg;
 use env_logger::Env;
 
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::PathBuf;
 use std::sync::Mutex;
 use std::time::Duration;
+use tokio::{sync::Notify, time};
 use uuid::Uuid;
 use zoidberg_lib::types::{
-    FetchRequest, FetchResponse, Heartbeat, Job, RegisterResponse, Status, StatusRequest, Update,
-    Worker,
+    ArrayStatus, BatchStatusRequest, BatchStatusResponse, CancelRequest, DeregisterRequest,
+    FetchRequest, FetchResponse, Heartbeat, HeartbeatResponse, Job, JobArray, JobFilter, JobState,
+    Log, RegisterResponse, Role, Status, StatusRequest, TokenInfo, TokenRequest, TokenResponse,
+    Update, Worker, WorkflowState,
 };
 
 mod auth;
+mod config;
+mod dependencies;
+mod limits;
+mod retry;
+mod scheduler;
+mod storage;
+mod store;
+mod tls;
 mod webpage;
 
-use auth::Authorization;
+use auth::{Authorization, Token};
+use config::Config;
+use limits::Limits;
+use retry::RetryPolicy;
+use scheduler::{Policy, Scheduler};
+use storage::{Journal, Memory, Storage};
+use store::Store;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// Locks are taken in the order workers, jobs, logs.
 struct State {
-    counter_jobs: Mutex<i32>,
     workers: Mutex<Vec<Worker>>,
-    new_jobs: Mutex<Vec<Job>>,
-    jobs: Mutex<Vec<Job>>,
+    jobs: Mutex<Store>,
+    /// IDs of the jobs that have a log, the logs stay in `storage`.
+    logs: Mutex<HashSet<i32>>,
+    tokens: Mutex<Vec<Token>>,
+    storage: Box<dyn Storage>,
+    retry: RetryPolicy,
+    limits: Limits,
+    scheduler: Scheduler,
+    /// Wakes up the workers waiting in `/fetch` when jobs were queued.
+    queued: Notify,
+    /// Longest time in seconds a worker may wait in `/fetch`.
+    max_fetch_wait: u64,
 }
 
 impl State {
     fn new() -> Self {
         Self {
-            counter_jobs: Mutex::new(0),
             workers: Mutex::new(Vec::new()),
-            new_jobs: Mutex::new(Vec::new()),
-            jobs: Mutex::new(Vec::new()),
+            jobs: Mutex::new(Store::default()),
+            logs: Mutex::new(HashSet::new()),
+            tokens: Mutex::new(Vec::new()),
+            storage: Box::new(Memory::default()),
+            retry: RetryPolicy::default(),
+            limits: Limits::default(),
+            scheduler: Scheduler::default(),
+            queued: Notify::new(),
+            max_fetch_wait: 0,
         }
     }
+
+    fn restore(storage: Box<dyn Storage>) -> std::io::Result<Self> {
+        let snapshot = storage.load()?;
+        let mut jobs = Store::default();
+        jobs.counter = snapshot.counter_jobs;
+        jobs.extend(snapshot.jobs);
+        for id in dependencies::resolve(&mut jobs, Utc::now().timestamp()) {
+            storage.save_job(jobs.get(id).unwrap())?;
+        }
+
+        // restored workers get a fresh heartbeat, so that they have time to
+        // report back before the jobs running on them are reaped
+        let now = Utc::now().timestamp();
+        let workers: Vec<Worker> = snapshot
+            .workers
+            .into_iter()
+            .map(|w| Worker {
+                last_heartbeat: Some(now),
+                ..w
+            })
+            .collect();
+
+        log::info!(
+            "Restored {} job(s), {} of them queued, and {} worker(s)",
+            jobs.len(),
+            jobs.queued_len(),
+            workers.len()
+        );
+        Ok(Self {
+            workers: Mutex::new(workers),
+            jobs: Mutex::new(jobs),
+            logs: Mutex::new(snapshot.logs.into_iter().collect()),
+            tokens: Mutex::new(snapshot.tokens),
+            storage,
+            retry: RetryPolicy::default(),
+            limits: Limits::default(),
+            scheduler: Scheduler::default(),
+            queued: Notify::new(),
+            max_fetch_wait: 0,
+        })
+    }
+
+    /// Forgets workers that stopped sending heartbeats, ends or requeues the
+    /// jobs that were running on them and forgets jobs that finished longer
+    /// than `retention` seconds ago, unless unfinished jobs depend on them.
+    /// Compacts the storage on the way.
+    fn reap(&self, now: i64, worker_timeout: i64, retention: Option<i64>) {
+        if let Err(e) = self.storage.compact() {
+            log::error!("Could not compact storage: {}", e);
+        }
+        {
+            let mut workers = self.workers.lock().unwrap();
+            workers.retain(|w| {
+                let alive = match w.last_heartbeat {
+                    None => true,
+                    Some(t) => now - t < worker_timeout,
+                };
+                if !alive {
+                    if let Err(e) = self.storage.remove_worker(&w.id) {
+                        log::error!("Could not persist removal of worker {}: {}", w.id, e);
+                    }
+                }
+                alive
+            })
+        }
+        let workers = self.workers.lock().unwrap();
+        let mut jobs = self.jobs.lock().unwrap();
+        self.release_jobs(&workers, &mut jobs, now, "stopped sending heartbeats");
+        if let Err(e) = self.resolve_dependencies(&mut jobs) {
+            log::error!("Could not persist jobs with failed dependencies: {}", e);
+        }
+
+        if let Some(retention) = retention {
+            let mut logs = self.logs.lock().unwrap();
+            jobs.retain(|j| {
+                let expired =
+                    j.status.is_final() && j.finished_at.is_some_and(|t| now - t >= retention);
+                if expired {
+                    logs.remove(&j.id);
+                    if let Err(e) = self.storage.remove_job(j.id) {
+                        log::error!("Could not persist removal of job {}: {}", j.id, e);
+                    }
+                }
+                !expired
+            });
+        }
+    }
+
+    /// Ends cancelled jobs and fails, or requeues, running jobs whose
+    /// worker is no longer in `workers`, `reason` tells what happened to it.
+    fn release_jobs(&self, workers: &[Worker], jobs: &mut Store, now: i64, reason: &str) {
+        let lost: Vec<Job> = jobs
+            .holders()
+            .filter(|w| !workers.iter().any(|x| &x.id == *w))
+            .flat_map(|w| jobs.held(w).cloned())
+            .collect();
+        for mut job in lost {
+            let mut requeued = false;
+            if let Status::Running(w) = job.status.clone() {
+                // the job did not fail, it is queued again as long as it
+                // has retries left and the lost attempt is kept in its history
+                job.reason = Some(format!("worker {} {}", w, reason));
+                job.finished_at = Some(now);
+                if self.retry.retry(&mut job, true) {
+                    log::info!(
+                        "Requeued job {} of lost worker {}, attempt {}",
+                        job.id,
+                        w,
+                        job.attempt
+                    );
+                    requeued = true;
+                } else {
+                    job.status = Status::Failed;
+                }
+            } else {
+                // a cancelled job ends with the worker that had to kill it
+                job.finished_at = Some(now);
+            }
+            if let Err(e) = self.storage.save_job(&job) {
+                log::error!("Could not persist job {}: {}", job.id, e);
+            }
+            let id = job.id;
+            jobs.replace(job);
+            if requeued {
+                jobs.enqueue(id);
+            }
+        }
+    }
+
+    /// Queues jobs whose dependencies finished, persists the jobs that
+    /// ended because of failed dependencies and wakes up waiting workers.
+    fn resolve_dependencies(&self, jobs: &mut Store) -> std::io::Result<()> {
+        let ended = dependencies::resolve(jobs, Utc::now().timestamp());
+        if jobs.queued_len() > 0 {
+            self.queued.notify_waiters();
+        }
+        for id in ended {
+            self.storage.save_job(jobs.get(id).unwrap())?;
+        }
+        Ok(())
+    }
 }
 
 #[get("/")]
 async fn index(data: web::Data<State>) -> impl Responder {
     let workers = data.workers.lock().unwrap();
     let jobs = data.jobs.lock().unwrap();
+    let logs = data.logs.lock().unwrap();
     let filtered_jobs: Vec<Job> = jobs
-        .to_vec()
         .iter()
         .filter(|x| !matches!(x.status, Status::Completed))
         .cloned()
         .collect();
-    let page = webpage::render(&filtered_jobs, &*workers);
+    let page = webpage::render(&filtered_jobs, &workers, &logs);
     HttpResponse::Ok().body(page)
 }
 
 #[get("/register")]
-async fn register(data: web::Data<State>, _: Authorization) -> Result<impl Responder> {
+async fn register(data: web::Data<State>, auth: Authorization) -> Result<impl Responder> {
+    auth.require(Role::Worker)?;
     let mut workers = data.workers.lock().unwrap();
     let uuid = Uuid::new_v4().to_string();
-    workers.push(Worker {
+    let worker = Worker {
         id: uuid.clone(),
         last_heartbeat: None,
-    });
+        ..Default::default()
+    };
+    data.storage
+        .save_worker(&worker)
+        .map_err(ErrorInternalServerError)?;
+    workers.push(worker);
 
     log::info!("Registered worker node with id: {}", uuid);
     Ok(web::Json(RegisterResponse { id: uuid }))
 }
 
+/// Hands the best fitting queued jobs to the worker, as many as its
+/// resources and `max_jobs` allow, `None` if none fits.
+fn dispatch(data: &State, f: &FetchRequest) -> Result<Option<FetchResponse>> {
+    {
+        let workers = data.workers.lock().unwrap();
+        match workers.iter().find(|w| w.id == f.worker_id) {
+            None => return Ok(Some(FetchResponse::Terminate("Worker not found".into()))),
+            Some(w) if w.draining => {
+                return Ok(Some(FetchResponse::Terminate("Worker is drained".into())))
+            }
+            Some(_) => {}
+        }
+    }
+    let mut jobs = data.jobs.lock().unwrap();
+    let now = Utc::now().timestamp();
+
+    let mut free = f.free;
+    let mut fetched = Vec::new();
+    while fetched.len() < f.max_jobs.unwrap_or(1) as usize {
+        let id = match jobs.select(&data.scheduler, |x| free.fits(x), now) {
+            Some(id) => id,
+            None => break,
+        };
+        // the job stays queued unless it is persisted as running
+        let mut job = jobs.get(id).unwrap().clone();
+        job.status = Status::Running(f.worker_id.clone());
+        job.started_at = Some(now);
+        job.worker = Some(f.worker_id.clone());
+        if let Err(e) = data.storage.save_job(&job) {
+            if fetched.is_empty() {
+                return Err(ErrorInternalServerError(e));
+            }
+            // hand out the jobs that are already persisted as running
+            log::error!("Could not dispatch job {}: {}", id, e);
+            break;
+        }
+        jobs.dequeue(id);
+        data.scheduler.charge(&job, now);
+        free.take(&job);
+        jobs.replace(job.clone());
+        fetched.push(job);
+    }
+    match fetched.is_empty() {
+        true => Ok(None),
+        false => Ok(Some(FetchResponse::Jobs(fetched))),
+    }
+}
+
 #[post("/fetch")]
 async fn fetch(
     data: web::Data<State>,
     f: web::Json<FetchRequest>,
-    _: Authorization,
+    auth: Authorization,
 ) -> Result<impl Responder> {
+    auth.require(Role::Worker)?;
     let f = f.into_inner();
-    let requesting_worker = f.worker_id;
-    let threads = f.threads;
-    {
-        let workers = data.workers.lock().unwrap();
-        if workers.iter().filter(|w| w.id == requesting_worker).count() != 1 {
-            return Ok(web::Json(FetchResponse::Terminate(
-                "Worker not found".into(),
-            )));
+    // long-polling: hold the request until a job was queued or time is up
+    let wait = f.wait.unwrap_or(0).min(data.max_fetch_wait);
+    let deadline = time::Instant::now() + Duration::from_secs(wait);
+    loop {
+        let queued = data.queued.notified();
+        tokio::pin!(queued);
+        // register before looking at the queue to not miss a notification
+        queued.as_mut().enable();
+        if let Some(response) = dispatch(&data, &f)? {
+            return Ok(web::Json(response));
         }
-    }
-    let mut new_jobs = data.new_jobs.lock().unwrap();
-    new_jobs.sort_by(|a, b| b.threads.cmp(&a.threads));
-
-    if let Some(j) = new_jobs
-        .iter()
-        .filter(|x| x.threads <= threads)
-        .cloned()
-        .collect::<Vec<Job>>()
-        .first()
-    {
-        let mut jobs = data.jobs.lock().unwrap();
-        for cj in jobs.iter_mut() {
-            if cj.id == j.id {
-                cj.status = Status::Running(requesting_worker.clone())
-            }
+        if time::timeout_at(deadline, queued).await.is_err() {
+            return Ok(web::Json(FetchResponse::Nop));
         }
-        new_jobs.retain(|x| x.id != j.id);
-        return Ok(web::Json(FetchResponse::Jobs(vec![j.clone()])));
-    };
-    Ok(web::Json(FetchResponse::Nop))
+    }
 }
 
 #[post("/status")]
 async fn status(
     s: web::Json<Vec<StatusRequest>>,
     data: web::Data<State>,
-    _: Authorization,
+    auth: Authorization,
 ) -> Result<impl Responder> {
+    auth.require(Role::Submitter)?;
     let jobs = data.jobs.lock().unwrap();
-    let status_updates: Vec<Job> = jobs
-        .iter()
-        .filter(|r| s.iter().filter(|i| i.id == r.id).count() > 0)
+    let ids: BTreeSet<i32> = s.iter().map(|r| r.id).collect();
+    let status_updates: Vec<Job> = ids
+        .into_iter()
+        .filter_map(|id| jobs.get(id))
         .cloned()
         .collect();
 
     Ok(web::Json(status_updates))
 }
 
+#[post("/v1/status")]
+async fn batch_status(
+    request: web::Json<BatchStatusRequest>,
+    data: web::Data<State>,
+    auth: Authorization,
+) -> Result<impl Responder> {
+    auth.require(Role::Submitter)?;
+    let jobs = data.jobs.lock().unwrap();
+    let states = request
+        .ids
+        .iter()
+        .map(|&id| match jobs.get(id) {
+            Some(job) => JobState {
+                id,
+                state: WorkflowState::from(&job.status),
+                status: job.status.name().to_string(),
+                reason: job.reason.clone(),
+            },
+            None => JobState {
+                id,
+                state: WorkflowState::Failed,
+                status: String::from("unknown"),
+                reason: Some(String::from("unknown job")),
+            },
+        })
+        .collect();
+    Ok(web::Json(BatchStatusResponse { jobs: states }))
+}
+
+#[get("/jobs")]
+async fn list_jobs(
+    filter: web::Query<JobFilter>,
+    data: web::Data<State>,
+    auth: Authorization,
+) -> Result<impl Responder> {
+    auth.require(Role::Submitter)?;
+    let jobs = data.jobs.lock().unwrap();
+    let jobs: Vec<Job> = jobs.iter().filter(|j| filter.matches(j)).cloned().collect();
+    Ok(web::Json(jobs))
+}
+
+#[get("/workers")]
+async fn list_workers(data: web::Data<State>, auth: Authorization) -> Result<impl Responder> {
+    auth.require(Role::Submitter)?;
+    let workers = data.workers.lock().unwrap();
+    Ok(web::Json(workers.clone()))
+}
+
 #[post("/update")]
 async fn update(
     updates: web::Json<Vec<Update>>,
     data: web::Data<State>,
-    _: Authorization,
+    auth: Authorization,
 ) -> Result<String> {
+    auth.require(Role::Worker)?;
     let mut jobs = data.jobs.lock().unwrap();
+    let now = Utc::now().timestamp();
     let mut n = 0;
     for update in updates.iter() {
         log::info!(
@@ -134,100 +407,615 @@ async fn update(
             update.job,
             update.status
         );
-        for i in 0..jobs.len() {
-            if jobs[i].id == update.job {
-                jobs[i].status = update.status.clone();
-            }
+        // only the worker that runs the job may update it, anything else
+        // is a repeated update or comes from a worker that lost the job
+        let current = match jobs.get(update.job) {
+            Some(job) => match &job.status {
+                Status::Running(worker) => worker == &update.worker,
+                Status::Cancelled => job.worker.as_ref() == Some(&update.worker),
+                _ => false,
+            },
+            None => continue,
+        };
+        if !current {
+            log::warn!(
+                "Ignoring update of job {} from worker {}, which does not run it",
+                update.job,
+                update.worker
+            );
+            continue;
         }
         n += 1;
+        // the job is only changed once the change is persisted, so that a
+        // failed update can be repeated by the worker
+        let mut job = jobs.get(update.job).unwrap().clone();
+        let mut retried = false;
+        if matches!(job.status, Status::Cancelled) {
+            // the worker reports how the killed process ended
+            job.finished_at = Some(now);
+            job.exit_code = update.exit_code;
+            job.signal = update.signal;
+        } else {
+            job.status = update.status.clone();
+            if update.status.is_final() {
+                job.finished_at = Some(now);
+                job.exit_code = update.exit_code;
+                job.signal = update.signal;
+                job.reason = update.reason.clone();
+            }
+            retried = matches!(update.status, Status::Failed | Status::TimedOut)
+                && data.retry.retry(&mut job, false);
+            if retried {
+                log::info!("Retrying job {}, attempt {}", job.id, job.attempt);
+            }
+        }
+        data.storage
+            .save_job(&job)
+            .map_err(ErrorInternalServerError)?;
+        jobs.replace(job);
+        if retried {
+            jobs.enqueue(update.job);
+        }
     }
+    data.resolve_dependencies(&mut jobs)
+        .map_err(ErrorInternalServerError)?;
     Ok(format!("Worker updated {} job(s)", n))
 }
 
+#[post("/log")]
+async fn upload_log(
+    l: web::Json<Log>,
+    data: web::Data<State>,
+    auth: Authorization,
+) -> Result<String> {
+    auth.require(Role::Worker)?;
+    let l = l.into_inner();
+    log::info!("Worker {} uploaded log of job {}", l.worker, l.job);
+    let id = l.job;
+    let mut logs = data.logs.lock().unwrap();
+    data.storage
+        .save_log(&l)
+        .map_err(ErrorInternalServerError)?;
+    logs.insert(id);
+    Ok(format!("Stored log of job {}", id))
+}
+
+#[get("/log/{id}")]
+async fn job_log(
+    id: web::Path<i32>,
+    data: web::Data<State>,
+    auth: Authorization,
+) -> Result<impl Responder> {
+    auth.require(Role::Submitter)?;
+    Ok(web::Json(load_log(&data, id.into_inner())?))
+}
+
+/// Log of a job as a web page, browsers ask for a token as password.
+#[get("/log/{id}/view")]
+async fn view_log(
+    id: web::Path<i32>,
+    data: web::Data<State>,
+    auth: Option<Authorization>,
+) -> Result<HttpResponse> {
+    let auth = match auth {
+        Some(auth) => auth,
+        None => {
+            return Ok(HttpResponse::Unauthorized()
+                .insert_header(("WWW-Authenticate", "Basic realm=\"zoidberg\""))
+                .body("A token is needed to read logs"))
+        }
+    };
+    auth.require(Role::Submitter)?;
+    let log = load_log(&data, id.into_inner())?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(webpage::render_log(&log)))
+}
+
+fn load_log(data: &State, id: i32) -> Result<Log> {
+    let log = match data.logs.lock().unwrap().contains(&id) {
+        true => data
+            .storage
+            .load_log(id)
+            .map_err(ErrorInternalServerError)?,
+        false => None,
+    };
+    log.ok_or_else(|| ErrorNotFound(format!("No log for job {}", id)))
+}
+
+#[post("/deregister")]
+async fn deregister(
+    r: web::Json<DeregisterRequest>,
+    data: web::Data<State>,
+    auth: Authorization,
+) -> Result<String> {
+    auth.require(Role::Worker)?;
+    let mut workers = data.workers.lock().unwrap();
+    let mut jobs = data.jobs.lock().unwrap();
+    // a worker that already timed out is gone anyway
+    if workers.iter().any(|w| w.id == r.id) {
+        data.storage
+            .remove_worker(&r.id)
+            .map_err(ErrorInternalServerError)?;
+        workers.retain(|w| w.id != r.id);
+        log::info!("Deregistered worker {}", r.id);
+    }
+    data.release_jobs(&workers, &mut jobs, Utc::now().timestamp(), "shut down");
+    data.resolve_dependencies(&mut jobs)
+        .map_err(ErrorInternalServerError)?;
+    Ok(format!("Deregistered worker {}", r.id))
+}
+
+#[post("/workers/{id}/drain")]
+async fn drain_worker(
+    id: web::Path<String>,
+    data: web::Data<State>,
+    auth: Authorization,
+) -> Result<impl Responder> {
+    auth.require(Role::Admin)?;
+    let id = id.into_inner();
+    let mut workers = data.workers.lock().unwrap();
+    let worker = match workers.iter_mut().find(|w| w.id == id) {
+        Some(w) => w,
+        None => return Err(ErrorNotFound(format!("No worker {}", id))),
+    };
+    worker.draining = true;
+    data.storage
+        .save_worker(worker)
+        .map_err(ErrorInternalServerError)?;
+    log::info!("Draining worker {}", id);
+    // a worker waiting in /fetch is told to terminate right away
+    data.queued.notify_waiters();
+    Ok(web::Json(worker.clone()))
+}
+
 #[post("/heartbeat")]
 async fn heartbeat(
     heartbeat: web::Json<Heartbeat>,
     data: web::Data<State>,
-    _: Authorization,
-) -> Result<String> {
+    auth: Authorization,
+) -> Result<impl Responder> {
+    auth.require(Role::Worker)?;
     log::debug!("Heartbeat from worker {}", heartbeat.id);
-    let mut workers = data.workers.lock().unwrap();
-    for w in workers.iter_mut() {
-        if w.id == heartbeat.id {
-            w.last_heartbeat = Some(Utc::now().timestamp());
+    {
+        let mut workers = data.workers.lock().unwrap();
+        for w in workers.iter_mut() {
+            if w.id == heartbeat.id {
+                w.last_heartbeat = Some(Utc::now().timestamp());
+            }
         }
     }
-    Ok(format!("Heartbeat from worker {}", heartbeat.id))
+    let jobs = data.jobs.lock().unwrap();
+    let to_kill: Vec<i32> = jobs
+        .held(&heartbeat.id)
+        .filter(|j| matches!(j.status, Status::Cancelled))
+        .map(|j| j.id)
+        .collect();
+    Ok(web::Json(HeartbeatResponse { cancel: to_kill }))
 }
 
-#[post("/submit")]
-async fn submit(
+/// Cancels the jobs that are not finished yet and returns them.
+fn cancel_jobs(data: &State, jobs: &mut Store, ids: BTreeSet<i32>) -> Result<Vec<Job>> {
+    let now = Utc::now().timestamp();
+    let mut cancelled = Vec::new();
+    for id in ids {
+        if jobs.get(id).is_none_or(|j| j.status.is_final()) {
+            continue;
+        }
+        jobs.dequeue(id);
+        let mut job = jobs.get(id).unwrap().clone();
+        if matches!(job.status, Status::Submitted) {
+            job.finished_at = Some(now);
+        }
+        // running jobs are finished once their worker killed the process
+        log::info!("Cancelled job {}", job.id);
+        job.status = Status::Cancelled;
+        job.reason = Some(String::from("cancelled"));
+        data.storage
+            .save_job(&job)
+            .map_err(ErrorInternalServerError)?;
+        jobs.replace(job.clone());
+        cancelled.push(job);
+    }
+    data.resolve_dependencies(jobs)
+        .map_err(ErrorInternalServerError)?;
+    Ok(cancelled)
+}
+
+#[post("/cancel")]
+async fn cancel(
+    c: web::Json<Vec<CancelRequest>>,
     data: web::Data<State>,
-    js: web::Json<Vec<Job>>,
-    _: Authorization,
+    auth: Authorization,
 ) -> Result<impl Responder> {
-    let mut new_jobs = data.new_jobs.lock().unwrap();
+    auth.require(Role::Submitter)?;
     let mut jobs = data.jobs.lock().unwrap();
-    let mut counter_jobs = data.counter_jobs.lock().unwrap();
-    let mut new_new_jobs = Vec::new();
-    for j in js.into_inner() {
-        *counter_jobs += 1;
+    let ids: BTreeSet<i32> = c.iter().map(|r| r.id).collect();
+    Ok(web::Json(cancel_jobs(&data, &mut jobs, ids)?))
+}
+
+/// Validates, numbers and queues the jobs of a submission.
+fn submit_jobs(
+    data: &State,
+    jobs: &mut Store,
+    js: Vec<Job>,
+    auth: &Authorization,
+) -> Result<Vec<Job>> {
+    let now = Utc::now().timestamp();
+    let mut id = jobs.counter;
+    let mut new_new_jobs: Vec<Job> = Vec::new();
+    // IDs given in the submission refer to jobs of the same submission
+    let mut labels: HashMap<i32, i32> = HashMap::new();
+    for mut j in js {
+        j.validate()
+            .and_then(|_| data.limits.apply(&mut j))
+            .map_err(|e| ErrorBadRequest(format!("Invalid job {}: {}", j.cmd, e)))?;
+        if j.cmd.is_empty() {
+            j.cmd = j.argv.join(" ");
+        }
+        id += 1;
+        let mut after = Vec::new();
+        for parent in j.after.iter() {
+            let parent = *labels.get(parent).unwrap_or(parent);
+            let known = new_new_jobs.iter().any(|x| x.id == parent) || jobs.get(parent).is_some();
+            if !known {
+                return Err(ErrorBadRequest(format!(
+                    "Job {} depends on unknown job {}",
+                    j.cmd, parent
+                )));
+            }
+            after.push(parent);
+        }
+        if j.id != 0 {
+            labels.insert(j.id, id);
+        }
         let cmd = j.cmd.clone();
-        log::info!("Job submitted with id: {}, cmd: {}", *counter_jobs, cmd);
+        log::info!(
+            "Job submitted by {} with id: {}, cmd: {}",
+            auth.name,
+            id,
+            cmd
+        );
+        // only admins may submit jobs on behalf of others
+        let owner = if auth.role == Role::Admin && !j.owner.is_empty() {
+            j.owner.clone()
+        } else {
+            auth.name.clone()
+        };
 
         new_new_jobs.push(Job {
-            id: *counter_jobs,
+            id,
+            after,
+            owner,
+            status: Status::Submitted,
+            submitted_at: Some(now),
+            started_at: None,
+            finished_at: None,
+            worker: None,
+            exit_code: None,
+            signal: None,
+            reason: None,
+            attempt: 1,
+            history: Vec::new(),
             ..j
         });
     }
-    for job in new_new_jobs.iter() {
-        new_jobs.push(job.clone());
-        jobs.push(job.clone());
+    jobs.counter = id;
+    data.storage
+        .save_counter(id)
+        .map_err(ErrorInternalServerError)?;
+    let ids: Vec<i32> = new_new_jobs.iter().map(|j| j.id).collect();
+    for job in new_new_jobs.into_iter() {
+        data.storage
+            .save_job(&job)
+            .map_err(ErrorInternalServerError)?;
+        jobs.insert(job);
+    }
+    data.resolve_dependencies(jobs)
+        .map_err(ErrorInternalServerError)?;
+    Ok(ids
+        .iter()
+        .map(|id| jobs.get(*id).unwrap().clone())
+        .collect())
+}
+
+#[post("/submit")]
+async fn submit(
+    data: web::Data<State>,
+    js: web::Json<Vec<Job>>,
+    auth: Authorization,
+) -> Result<impl Responder> {
+    auth.require(Role::Submitter)?;
+    let mut jobs = data.jobs.lock().unwrap();
+    // tasks of job arrays are only created by /arrays
+    let js = js
+        .into_inner()
+        .into_iter()
+        .map(|j| Job {
+            array_id: None,
+            array_index: None,
+            ..j
+        })
+        .collect();
+    Ok(web::Json(submit_jobs(&data, &mut jobs, js, &auth)?))
+}
+
+#[post("/arrays")]
+async fn submit_array(
+    data: web::Data<State>,
+    a: web::Json<JobArray>,
+    auth: Authorization,
+) -> Result<impl Responder> {
+    auth.require(Role::Submitter)?;
+    let tasks = a.tasks().map_err(ErrorBadRequest)?;
+    let mut jobs = data.jobs.lock().unwrap();
+    // the array is named after its first task
+    let array = jobs.counter + 1;
+    let tasks = tasks
+        .into_iter()
+        .map(|j| Job {
+            array_id: Some(array),
+            ..j
+        })
+        .collect();
+    let submitted = submit_jobs(&data, &mut jobs, tasks, &auth)?;
+    log::info!(
+        "Job array {} with {} task(s) submitted by {}",
+        array,
+        submitted.len(),
+        auth.name
+    );
+    Ok(web::Json(submitted))
+}
+
+/// Counts the tasks of a job array, fails if no task is known.
+fn array_status(jobs: &Store, id: i32) -> Result<ArrayStatus> {
+    let mut summary = ArrayStatus {
+        id,
+        ..Default::default()
+    };
+    for job in jobs.array(id) {
+        summary.add(job);
+    }
+    match summary.tasks {
+        0 => Err(ErrorNotFound(format!("No job array {}", id))),
+        _ => Ok(summary),
+    }
+}
+
+#[get("/arrays/{id}")]
+async fn get_array(
+    id: web::Path<i32>,
+    data: web::Data<State>,
+    auth: Authorization,
+) -> Result<impl Responder> {
+    auth.require(Role::Submitter)?;
+    let jobs = data.jobs.lock().unwrap();
+    Ok(web::Json(array_status(&jobs, id.into_inner())?))
+}
+
+#[post("/arrays/{id}/cancel")]
+async fn cancel_array(
+    id: web::Path<i32>,
+    data: web::Data<State>,
+    auth: Authorization,
+) -> Result<impl Responder> {
+    auth.require(Role::Submitter)?;
+    let id = id.into_inner();
+    let mut jobs = data.jobs.lock().unwrap();
+    array_status(&jobs, id)?;
+    let ids: BTreeSet<i32> = jobs.array(id).map(|j| j.id).collect();
+    log::info!("Cancelling job array {}", id);
+    Ok(web::Json(cancel_jobs(&data, &mut jobs, ids)?))
+}
+
+#[post("/tokens")]
+async fn create_token(
+    t: web::Json<TokenRequest>,
+    data: web::Data<State>,
+    auth: Authorization,
+) -> Result<impl Responder> {
+    auth.require(Role::Admin)?;
+    let t = t.into_inner();
+    let mut tokens = data.tokens.lock().unwrap();
+    if tokens.iter().any(|x| x.name == t.name) {
+        return Err(ErrorBadRequest(format!("Token {} already exists", t.name)));
+    }
+    let secret = auth::generate();
+    let token = Token {
+        name: t.name.clone(),
+        role: t.role,
+        hash: auth::hash(&secret),
+        created_at: Utc::now().timestamp(),
+    };
+    data.storage
+        .save_token(&token)
+        .map_err(ErrorInternalServerError)?;
+    tokens.push(token);
+    log::info!("Created {} token {}", t.role, t.name);
+    Ok(web::Json(TokenResponse {
+        name: t.name,
+        role: t.role,
+        token: secret,
+    }))
+}
+
+#[get("/tokens")]
+async fn list_tokens(data: web::Data<State>, auth: Authorization) -> Result<impl Responder> {
+    auth.require(Role::Admin)?;
+    let tokens = data.tokens.lock().unwrap();
+    let infos: Vec<TokenInfo> = tokens
+        .iter()
+        .map(|t| TokenInfo {
+            name: t.name.clone(),
+            role: t.role,
+            created_at: t.created_at,
+        })
+        .collect();
+    Ok(web::Json(infos))
+}
+
+#[delete("/tokens/{name}")]
+async fn revoke_token(
+    name: web::Path<String>,
+    data: web::Data<State>,
+    auth: Authorization,
+) -> Result<String> {
+    auth.require(Role::Admin)?;
+    let name = name.into_inner();
+    let mut tokens = data.tokens.lock().unwrap();
+    if !tokens.iter().any(|t| t.name == name) {
+        return Err(ErrorNotFound(format!("No token {}", name)));
     }
-    Ok(web::Json(new_new_jobs))
+    data.storage
+        .remove_token(&name)
+        .map_err(ErrorInternalServerError)?;
+    tokens.retain(|t| t.name != name);
+    log::info!("Revoked token {}", name);
+    Ok(format!("Revoked token {}", name))
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     env_logger::Builder::from_env(Env::default().default_filter_or("zoidberg_server=info")).init();
 
-    let secret = std::env::var("ZOIDBERG_SECRET").unwrap_or_else(|_| {
-        eprintln!("Please set the $ZOIDBERG_SECRET environment variable");
-        std::process::exit(1);
-    });
-
-    let _matches = clap::App::new("Zoidberg server")
+    let matches = clap::App::new("Zoidberg server")
         .version(VERSION)
         .author("by Johannes Heuel")
+        .arg(
+            arg!(-c --config <FILE> "Read the configuration from this TOML file")
+                .required(false)
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(-s --storage <FILE> "Persist jobs and workers in this journal file")
+                .required(false)
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(--"max-retries" <N> "Default number of retries of failed jobs")
+                .required(false)
+                .value_parser(clap::value_parser!(i32)),
+        )
+        .arg(
+            arg!(--"max-lost-worker-retries" <N> "Requeues of jobs whose worker was lost, 3 by default")
+                .required(false)
+                .value_parser(clap::value_parser!(i32)),
+        )
+        .arg(
+            arg!(--scheduler <POLICY> "Order of queued jobs: priority, fifo or fair-share")
+                .required(false)
+                .value_parser(clap::value_parser!(Policy)),
+        )
+        .arg(arg!(-b --bind <ADDRESS> "Address to listen on").required(false))
+        .arg(
+            arg!(-p --port <PORT> "Port to listen on")
+                .required(false)
+                .value_parser(clap::value_parser!(u16)),
+        )
+        .arg(
+            arg!(--"tls-cert" <FILE> "PEM encoded certificate chain, enables TLS")
+                .required(false)
+                .requires("tls-key")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(--"tls-key" <FILE> "PEM encoded private key of the certificate")
+                .required(false)
+                .requires("tls-cert")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
         .get_matches();
 
-    let state = web::Data::new(State::new());
+    let mut config = match matches.get_one::<PathBuf>("config") {
+        Some(path) => Config::from_file(path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }),
+        None => Config::default(),
+    };
+    if let Err(e) = config.apply_env(|k| std::env::var(k).ok()) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    // command line options take precedence over file and environment
+    if let Some(path) = matches.get_one::<PathBuf>("storage") {
+        config.storage.journal = Some(path.clone());
+    }
+    if let Some(n) = matches.get_one::<i32>("max-retries") {
+        config.retry.max_retries = *n;
+    }
+    if let Some(n) = matches.get_one::<i32>("max-lost-worker-retries") {
+        config.retry.max_lost_worker_retries = *n;
+    }
+    if let Some(policy) = matches.get_one::<Policy>("scheduler") {
+        config.scheduler.policy = *policy;
+    }
+    let bind = matches.get_one::<String>("bind");
+    let port = matches.get_one::<u16>("port");
+    if bind.is_some() || port.is_some() {
+        config.listen = vec![format!(
+            "{}:{}",
+            bind.map_or("0.0.0.0", |b| b.as_str()),
+            port.unwrap_or(&8080)
+        )];
+    }
+    if let (Some(cert), Some(key)) = (
+        matches.get_one::<PathBuf>("tls-cert"),
+        matches.get_one::<PathBuf>("tls-key"),
+    ) {
+        config.tls = Some(config::Tls {
+            cert: cert.clone(),
+            key: key.clone(),
+        });
+    }
+    if let Err(errors) = config.validate() {
+        for e in errors {
+            eprintln!("Invalid configuration: {}", e);
+        }
+        std::process::exit(1);
+    }
+    let secret = config.auth.secret.clone().unwrap_or_default();
+
+    let mut state = if let Some(path) = &config.storage.journal {
+        log::info!("Using journal {}", path.display());
+        State::restore(Box::new(Journal::open(path)?))?
+    } else {
+        State::new()
+    };
+    state.retry = RetryPolicy {
+        max_retries: config.retry.max_retries,
+        max_lost_worker_retries: config.retry.max_lost_worker_retries,
+    };
+    state.limits = Limits {
+        default_walltime: config.limits.default_walltime.map(|w| w as i64),
+        max_walltime: config.limits.max_walltime.map(|w| w as i64),
+    };
+    state.scheduler = Scheduler::new(config.scheduler.policy, config.scheduler.half_life);
+    state
+        .jobs
+        .get_mut()
+        .unwrap()
+        .set_policy(config.scheduler.policy);
+    state.max_fetch_wait = config.timeouts.max_fetch_wait;
+    let state = web::Data::new(state);
 
     let s = state.clone();
+    let reap_interval = Duration::from_secs(config.timeouts.reap_interval);
+    let worker_timeout = config.timeouts.worker as i64;
+    let retention = config.retention.finished_jobs.map(|r| r as i64);
     tokio::spawn(async move {
         loop {
-            tokio::time::sleep(Duration::from_secs(10)).await;
-            {
-                let mut workers = s.workers.lock().unwrap();
-                workers.retain(|w| match w.last_heartbeat {
-                    None => true,
-                    Some(t) => Utc::now().timestamp() - t < 60,
-                })
-            }
-            let workers = s.workers.lock().unwrap();
-            let mut jobs = s.jobs.lock().unwrap();
-            for job in jobs.iter_mut() {
-                if let Status::Running(w) = &job.status {
-                    let exists = workers.iter().filter(|x| &x.id == w).count() > 0;
-                    if !exists {
-                        job.status = Status::Failed;
-                    }
-                }
-            }
+            tokio::time::sleep(reap_interval).await;
+            s.reap(Utc::now().timestamp(), worker_timeout, retention);
         }
     });
 
-    HttpServer::new(move || {
+    let tls = match &config.tls {
+        Some(tls) => Some(tls::load_config(&tls.cert, &tls.key)?),
+        None => None,
+    };
+    let mut server = HttpServer::new(move || {
         App::new()
             .wrap(Logger::default())
             .app_data(secret.clone())
@@ -236,20 +1024,45 @@ async fn main() -> std::io::Result<()> {
             .service(register)
             .service(fetch)
             .service(status)
+            .service(batch_status)
+            .service(list_jobs)
+            .service(list_workers)
+            .service(drain_worker)
             .service(update)
             .service(heartbeat)
+            .service(deregister)
             .service(submit)
-    })
-    .bind(("0.0.0.0", 8080))?
-    .run()
-    .await
+            .service(cancel)
+            .service(submit_array)
+            .service(get_array)
+            .service(cancel_array)
+            .service(upload_log)
+            .service(job_log)
+            .service(view_log)
+            .service(create_token)
+            .service(list_tokens)
+            .service(revoke_token)
+    });
+    for address in config.listen.iter() {
+        server = match &tls {
+            Some(tls) => {
+                log::info!("Listening on https://{}", address);
+                server.bind_rustls(address.as_str(), tls.clone())?
+            }
+            None => {
+                log::info!("Listening on http://{}", address);
+                server.bind(address.as_str())?
+            }
+        };
+    }
+    server.run().await
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use actix_web::{http, test, web, App};
-    use zoidberg_lib::types::Status;
+    use zoidberg_lib::types::{ArrayParams, Attempt, Resources, Status};
 
     #[actix_web::test]
     async fn test_index() {
@@ -274,7 +1087,7 @@ mod tests {
         )
         .await;
         let req = test::TestRequest::get()
-            .append_header(("cookie", "secret"))
+            .append_header(("Authorization", "Bearer secret"))
             .uri("/register")
             .to_request();
         let resp: RegisterResponse = test::call_and_read_body_json(&app, req).await;
@@ -289,41 +1102,61 @@ mod tests {
             App::new()
                 .app_data(String::from("secret"))
                 .app_data(web::Data::new(State {
-                    counter_jobs: Mutex::new(0),
                     workers: Mutex::new(vec![Worker {
                         id: "some_worker".to_string(),
                         last_heartbeat: None,
+                        ..Default::default()
                     }]),
-                    new_jobs: Mutex::new(vec![
-                        Job {
-                            id: jobid,
-                            cmd: cmd.clone(),
-                            status: Status::Submitted,
-                            threads: 1,
-                        },
-                        Job {
-                            id: jobid + 1,
-                            cmd: cmd.clone(),
-                            status: Status::Submitted,
-                            threads: 2,
-                        },
-                        Job {
-                            id: jobid + 2,
-                            cmd: cmd.clone(),
-                            status: Status::Submitted,
-                            threads: 3,
-                        },
-                    ]),
-                    jobs: Mutex::new(Vec::new()),
+                    jobs: Mutex::new(
+                        vec![
+                            Job {
+                                id: jobid,
+                                cmd: cmd.clone(),
+                                status: Status::Submitted,
+                                threads: 1,
+                                ..Default::default()
+                            },
+                            Job {
+                                id: jobid + 1,
+                                cmd: cmd.clone(),
+                                status: Status::Submitted,
+                                threads: 2,
+                                ..Default::default()
+                            },
+                            Job {
+                                id: jobid + 2,
+                                cmd: cmd.clone(),
+                                status: Status::Submitted,
+                                threads: 3,
+                                ..Default::default()
+                            },
+                        ]
+                        .into_iter()
+                        .collect(),
+                    ),
+                    logs: Mutex::new(HashSet::new()),
+                    tokens: Mutex::new(Vec::new()),
+                    storage: Box::new(Memory::default()),
+                    retry: RetryPolicy::default(),
+                    limits: Limits::default(),
+                    scheduler: Scheduler::default(),
+                    queued: Notify::new(),
+                    max_fetch_wait: 0,
                 }))
                 .service(fetch),
         )
         .await;
         let req = test::TestRequest::post()
-            .append_header(("cookie", "secret"))
+            .append_header(("Authorization", "Bearer secret"))
             .set_json(FetchRequest {
                 worker_id: "some_worker".to_string(),
-                threads: 1,
+                free: Resources {
+                    threads: 1,
+                    memory: None,
+                    disk: None,
+                },
+                wait: None,
+                max_jobs: None,
             })
             .uri("/fetch")
             .to_request();
@@ -343,50 +1176,510 @@ mod tests {
     }
 
     #[actix_web::test]
-    async fn test_status() {
-        let cmd = String::from("hi");
-        let jobid = 1;
+    async fn test_fetch_memory() {
+        let state = State::new();
+        state.workers.lock().unwrap().push(Worker {
+            id: "some_worker".to_string(),
+            last_heartbeat: None,
+            ..Default::default()
+        });
+        for (id, memory) in [(1, 8192), (2, 2048)] {
+            state.jobs.lock().unwrap().insert(Job {
+                id,
+                cmd: String::from("hi"),
+                threads: 1,
+                memory,
+                ..Default::default()
+            });
+        }
         let app = test::init_service(
             App::new()
                 .app_data(String::from("secret"))
-                .app_data(web::Data::new(State {
-                    counter_jobs: Mutex::new(0),
-                    workers: Mutex::new(Vec::new()),
-                    new_jobs: Mutex::new(Vec::new()),
-                    jobs: Mutex::new(vec![Job {
-                        id: jobid,
-                        cmd: cmd.clone(),
-                        status: Status::Submitted,
-                        threads: 1,
-                    }]),
-                }))
-                .service(status),
+                .app_data(web::Data::new(state))
+                .service(fetch),
         )
         .await;
         let req = test::TestRequest::post()
-            .append_header(("cookie", "secret"))
-            .set_json(vec![StatusRequest { id: jobid }])
-            .uri("/status")
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(FetchRequest {
+                worker_id: "some_worker".to_string(),
+                free: Resources {
+                    threads: 1,
+                    memory: Some(4096),
+                    disk: None,
+                },
+                wait: None,
+                max_jobs: None,
+            })
+            .uri("/fetch")
             .to_request();
-        let resp: Vec<Job> = test::call_and_read_body_json(&app, req).await;
-        assert_eq!(resp[0].id, jobid);
-    }
+        let resp: FetchResponse = test::call_and_read_body_json(&app, req).await;
+        match resp {
+            FetchResponse::Jobs(new_jobs) => assert_eq!(new_jobs[0].id, 2),
+            _ => panic!("expected FetchResponse::Jobs"),
+        }
 
-    #[actix_web::test]
-    async fn test_update() {
-        let app = test::init_service(
-            App::new()
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(FetchRequest {
+                worker_id: "some_worker".to_string(),
+                free: Resources {
+                    threads: 1,
+                    memory: Some(4096),
+                    disk: None,
+                },
+                wait: None,
+                max_jobs: None,
+            })
+            .uri("/fetch")
+            .to_request();
+        let resp: FetchResponse = test::call_and_read_body_json(&app, req).await;
+        assert!(matches!(resp, FetchResponse::Nop));
+    }
+
+    #[actix_web::test]
+    async fn test_fetch_fair_share() {
+        let mut state = State::new();
+        state.scheduler = Scheduler::new(Policy::FairShare, 3600);
+        state.workers.lock().unwrap().push(Worker {
+            id: "some_worker".to_string(),
+            last_heartbeat: None,
+            ..Default::default()
+        });
+        for (id, owner) in [(1, "alice"), (2, "alice"), (3, "alice"), (4, "bob")] {
+            state.jobs.lock().unwrap().insert(Job {
+                id,
+                cmd: String::from("hi"),
+                threads: 1,
+                owner: owner.to_string(),
+                ..Default::default()
+            });
+        }
+        let app = test::init_service(
+            App::new()
+                .app_data(String::from("secret"))
+                .app_data(web::Data::new(state))
+                .service(fetch),
+        )
+        .await;
+        let mut fetched = Vec::new();
+        for _ in 0..2 {
+            let req = test::TestRequest::post()
+                .append_header(("Authorization", "Bearer secret"))
+                .set_json(FetchRequest {
+                    worker_id: "some_worker".to_string(),
+                    free: Resources {
+                        threads: 1,
+                        memory: None,
+                        disk: None,
+                    },
+                    wait: None,
+                    max_jobs: None,
+                })
+                .uri("/fetch")
+                .to_request();
+            match test::call_and_read_body_json(&app, req).await {
+                FetchResponse::Jobs(jobs) => fetched.push(jobs[0].id),
+                _ => panic!("expected FetchResponse::Jobs"),
+            }
+        }
+        assert_eq!(fetched, vec![1, 4]);
+    }
+
+    #[actix_web::test]
+    async fn test_fetch_batch() {
+        let state = State::new();
+        state.workers.lock().unwrap().push(Worker {
+            id: "some_worker".to_string(),
+            ..Default::default()
+        });
+        let queued: Vec<Job> = (1..=4)
+            .map(|id| Job {
+                id,
+                threads: if id == 1 { 2 } else { 1 },
+                ..Default::default()
+            })
+            .collect();
+        state.jobs.lock().unwrap().extend(queued);
+        let data = web::Data::new(state);
+        let app = test::init_service(
+            App::new()
+                .app_data(String::from("secret"))
+                .app_data(data.clone())
+                .service(fetch),
+        )
+        .await;
+        let request = |max_jobs| {
+            test::TestRequest::post()
+                .append_header(("Authorization", "Bearer secret"))
+                .set_json(FetchRequest {
+                    worker_id: "some_worker".to_string(),
+                    free: Resources {
+                        threads: 3,
+                        memory: None,
+                        disk: None,
+                    },
+                    wait: None,
+                    max_jobs,
+                })
+                .uri("/fetch")
+                .to_request()
+        };
+
+        // the two thread job and one more fill the three threads
+        let resp: FetchResponse = test::call_and_read_body_json(&app, request(Some(10))).await;
+        match resp {
+            FetchResponse::Jobs(jobs) => {
+                assert_eq!(jobs.iter().map(|j| j.id).collect::<Vec<_>>(), vec![1, 2])
+            }
+            _ => panic!("expected FetchResponse::Jobs"),
+        }
+        let resp: FetchResponse = test::call_and_read_body_json(&app, request(None)).await;
+        match resp {
+            FetchResponse::Jobs(jobs) => assert_eq!(jobs.len(), 1),
+            _ => panic!("expected FetchResponse::Jobs"),
+        }
+        let jobs = data.jobs.lock().unwrap();
+        assert_eq!(jobs.queued_len(), 1);
+        assert!(matches!(jobs.get(2).unwrap().status, Status::Running(_)));
+    }
+
+    #[actix_web::test]
+    async fn test_fetch_wait() {
+        let mut state = State::new();
+        state.max_fetch_wait = 10;
+        state.workers.lock().unwrap().push(Worker {
+            id: "some_worker".to_string(),
+            ..Default::default()
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(String::from("secret"))
+                .app_data(web::Data::new(state))
+                .service(fetch)
+                .service(submit),
+        )
+        .await;
+        let fetch_req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(FetchRequest {
+                worker_id: "some_worker".to_string(),
+                free: Resources {
+                    threads: 1,
+                    memory: None,
+                    disk: None,
+                },
+                wait: Some(10),
+                max_jobs: None,
+            })
+            .uri("/fetch")
+            .to_request();
+        let submit_req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(vec![Job {
+                cmd: String::from("hi"),
+                threads: 1,
+                ..Default::default()
+            }])
+            .uri("/submit")
+            .to_request();
+
+        // the waiting fetch gets the job as soon as it is submitted
+        let started = std::time::Instant::now();
+        let (resp, _) = futures::join!(
+            test::call_and_read_body_json::<_, _, FetchResponse>(&app, fetch_req),
+            async {
+                time::sleep(Duration::from_millis(100)).await;
+                test::call_service(&app, submit_req).await
+            }
+        );
+        assert!(matches!(resp, FetchResponse::Jobs(_)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[actix_web::test]
+    async fn test_status() {
+        let cmd = String::from("hi");
+        let jobid = 1;
+        let app = test::init_service(
+            App::new()
+                .app_data(String::from("secret"))
+                .app_data(web::Data::new(State {
+                    workers: Mutex::new(Vec::new()),
+                    jobs: Mutex::new(
+                        vec![Job {
+                            id: jobid,
+                            cmd: cmd.clone(),
+                            status: Status::Submitted,
+                            threads: 1,
+                            ..Default::default()
+                        }]
+                        .into_iter()
+                        .collect(),
+                    ),
+                    logs: Mutex::new(HashSet::new()),
+                    tokens: Mutex::new(Vec::new()),
+                    storage: Box::new(Memory::default()),
+                    retry: RetryPolicy::default(),
+                    limits: Limits::default(),
+                    scheduler: Scheduler::default(),
+                    queued: Notify::new(),
+                    max_fetch_wait: 0,
+                }))
+                .service(status),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(vec![StatusRequest { id: jobid }])
+            .uri("/status")
+            .to_request();
+        let resp: Vec<Job> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp[0].id, jobid);
+    }
+
+    #[actix_web::test]
+    async fn test_batch_status() {
+        let state = State::new();
+        state.jobs.lock().unwrap().extend([
+            Job {
+                id: 1,
+                status: Status::Running("some_worker".to_string()),
+                ..Default::default()
+            },
+            Job {
+                id: 2,
+                status: Status::Completed,
+                ..Default::default()
+            },
+            Job {
+                id: 3,
+                status: Status::Cancelled,
+                reason: Some(String::from("cancelled")),
+                ..Default::default()
+            },
+        ]);
+        let app = test::init_service(
+            App::new()
+                .app_data(String::from("secret"))
+                .app_data(web::Data::new(state))
+                .service(batch_status),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(BatchStatusRequest {
+                ids: vec![3, 1, 2, 4],
+            })
+            .uri("/v1/status")
+            .to_request();
+        let resp: BatchStatusResponse = test::call_and_read_body_json(&app, req).await;
+        let states: Vec<(i32, WorkflowState)> = resp.jobs.iter().map(|j| (j.id, j.state)).collect();
+        assert_eq!(
+            states,
+            vec![
+                (3, WorkflowState::Failed),
+                (1, WorkflowState::Running),
+                (2, WorkflowState::Success),
+                (4, WorkflowState::Failed),
+            ]
+        );
+        assert_eq!(resp.jobs[1].status, "running");
+        assert_eq!(resp.jobs[3].status, "unknown");
+
+        // the states are serialized the way Snakemake expects them
+        let json = serde_json::to_value(&resp.jobs[2]).unwrap();
+        assert_eq!(json["state"], "success");
+    }
+
+    #[actix_web::test]
+    async fn test_list_jobs() {
+        let state = State::new();
+        state.workers.lock().unwrap().push(Worker {
+            id: "some_worker".to_string(),
+            last_heartbeat: None,
+            ..Default::default()
+        });
+        state.jobs.lock().unwrap().extend([
+            Job {
+                id: 1,
+                status: Status::Running("some_worker".to_string()),
+                owner: String::from("alice"),
+                ..Default::default()
+            },
+            Job {
+                id: 2,
+                status: Status::Failed,
+                owner: String::from("bob"),
+                ..Default::default()
+            },
+        ]);
+        let app = test::init_service(
+            App::new()
+                .app_data(String::from("secret"))
+                .app_data(web::Data::new(state))
+                .service(list_jobs)
+                .service(list_workers),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .append_header(("Authorization", "Bearer secret"))
+            .uri("/jobs")
+            .to_request();
+        let resp: Vec<Job> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.len(), 2);
+
+        let req = test::TestRequest::get()
+            .append_header(("Authorization", "Bearer secret"))
+            .uri("/jobs?status=running&owner=alice")
+            .to_request();
+        let resp: Vec<Job> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.len(), 1);
+        assert_eq!(resp[0].id, 1);
+
+        let req = test::TestRequest::get()
+            .append_header(("Authorization", "Bearer secret"))
+            .uri("/jobs?owner=carol")
+            .to_request();
+        let resp: Vec<Job> = test::call_and_read_body_json(&app, req).await;
+        assert!(resp.is_empty());
+
+        let req = test::TestRequest::get()
+            .append_header(("Authorization", "Bearer secret"))
+            .uri("/workers")
+            .to_request();
+        let resp: Vec<Worker> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp[0].id, "some_worker");
+    }
+
+    #[actix_web::test]
+    async fn test_deregister() {
+        let state = State::new();
+        state.workers.lock().unwrap().extend([
+            Worker {
+                id: "some_worker".to_string(),
+                ..Default::default()
+            },
+            Worker {
+                id: "other_worker".to_string(),
+                ..Default::default()
+            },
+        ]);
+        state.jobs.lock().unwrap().extend([
+            Job {
+                id: 1,
+                status: Status::Running("some_worker".to_string()),
+                worker: Some("some_worker".to_string()),
+                ..Default::default()
+            },
+            Job {
+                id: 2,
+                status: Status::Running("other_worker".to_string()),
+                worker: Some("other_worker".to_string()),
+                ..Default::default()
+            },
+        ]);
+        let data = web::Data::new(state);
+        let app = test::init_service(
+            App::new()
+                .app_data(String::from("secret"))
+                .app_data(data.clone())
+                .service(deregister),
+        )
+        .await;
+        for _ in 0..2 {
+            let req = test::TestRequest::post()
+                .append_header(("Authorization", "Bearer secret"))
+                .uri("/deregister")
+                .set_json(DeregisterRequest {
+                    id: "some_worker".to_string(),
+                })
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert!(resp.status().is_success());
+        }
+
+        let workers = data.workers.lock().unwrap();
+        assert_eq!(workers.len(), 1);
+        assert_eq!(workers[0].id, "other_worker");
+        let jobs = data.jobs.lock().unwrap();
+        assert!(matches!(jobs.get(1).unwrap().status, Status::Submitted));
+        assert_eq!(
+            jobs.get(1).unwrap().history[0].reason.as_deref(),
+            Some("worker some_worker shut down")
+        );
+        assert!(matches!(jobs.get(2).unwrap().status, Status::Running(_)));
+    }
+
+    #[actix_web::test]
+    async fn test_drain_worker() {
+        let state = State::new();
+        state.workers.lock().unwrap().push(Worker {
+            id: "some_worker".to_string(),
+            ..Default::default()
+        });
+        state.jobs.lock().unwrap().insert(Job {
+            id: 1,
+            ..Default::default()
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(String::from("secret"))
+                .app_data(web::Data::new(state))
+                .service(drain_worker)
+                .service(fetch),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .uri("/workers/some_worker/drain")
+            .to_request();
+        let resp: Worker = test::call_and_read_body_json(&app, req).await;
+        assert!(resp.draining);
+
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .uri("/fetch")
+            .set_json(FetchRequest {
+                worker_id: "some_worker".to_string(),
+                free: Resources {
+                    threads: 1,
+                    memory: None,
+                    disk: None,
+                },
+                wait: None,
+                max_jobs: None,
+            })
+            .to_request();
+        let resp: FetchResponse = test::call_and_read_body_json(&app, req).await;
+        assert!(matches!(resp, FetchResponse::Terminate(_)));
+
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .uri("/workers/unknown/drain")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_update() {
+        let app = test::init_service(
+            App::new()
                 .app_data(String::from("secret"))
                 .app_data(web::Data::new(State::new()))
                 .service(update),
         )
         .await;
         let req = test::TestRequest::post()
-            .append_header(("cookie", "secret"))
+            .append_header(("Authorization", "Bearer secret"))
             .set_json(vec![Update {
                 worker: "some_worker".to_string(),
                 job: 0,
                 status: Status::Submitted,
+                exit_code: None,
+                signal: None,
+                reason: None,
             }])
             .uri("/update")
             .to_request();
@@ -394,6 +1687,663 @@ mod tests {
         assert_eq!(resp.status(), http::StatusCode::OK);
     }
 
+    #[actix_web::test]
+    async fn test_update_records_exit() {
+        let state = State::new();
+        state.jobs.lock().unwrap().insert(Job {
+            id: 1,
+            cmd: String::from("false"),
+            status: Status::Running("some_worker".to_string()),
+            threads: 1,
+            started_at: Some(0),
+            worker: Some("some_worker".to_string()),
+            ..Default::default()
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(String::from("secret"))
+                .app_data(web::Data::new(state))
+                .service(update)
+                .service(status),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(vec![Update {
+                worker: "some_worker".to_string(),
+                job: 1,
+                status: Status::Failed,
+                exit_code: Some(1),
+                signal: None,
+                reason: Some(String::from("exited with code 1")),
+            }])
+            .uri("/update")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(vec![StatusRequest { id: 1 }])
+            .uri("/status")
+            .to_request();
+        let resp: Vec<Job> = test::call_and_read_body_json(&app, req).await;
+        assert!(matches!(resp[0].status, Status::Failed));
+        assert_eq!(resp[0].exit_code, Some(1));
+        assert_eq!(resp[0].worker.as_deref(), Some("some_worker"));
+        assert!(resp[0].finished_at.is_some());
+    }
+
+    #[actix_web::test]
+    async fn test_update_retries() {
+        let mut state = State::new();
+        state.retry.max_retries = 1;
+        state.workers.lock().unwrap().push(Worker {
+            id: "some_worker".to_string(),
+            ..Default::default()
+        });
+        state.jobs.lock().unwrap().insert(Job {
+            id: 1,
+            cmd: String::from("false"),
+            status: Status::Running("some_worker".to_string()),
+            threads: 1,
+            attempt: 1,
+            ..Default::default()
+        });
+        let state = web::Data::new(state);
+        let app = test::init_service(
+            App::new()
+                .app_data(String::from("secret"))
+                .app_data(state.clone())
+                .service(update)
+                .service(fetch),
+        )
+        .await;
+        let failed = || Update {
+            worker: "some_worker".to_string(),
+            job: 1,
+            status: Status::Failed,
+            exit_code: Some(1),
+            signal: None,
+            reason: Some(String::from("exited with code 1")),
+        };
+
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(vec![failed()])
+            .uri("/update")
+            .to_request();
+        test::call_service(&app, req).await;
+        {
+            let jobs = state.jobs.lock().unwrap();
+            assert!(matches!(jobs.get(1).unwrap().status, Status::Submitted));
+            assert_eq!(jobs.get(1).unwrap().attempt, 2);
+            assert_eq!(jobs.get(1).unwrap().history[0].exit_code, Some(1));
+            assert_eq!(jobs.queued_len(), 1);
+        }
+
+        // a repeated update does not fail the requeued job again
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(vec![failed()])
+            .uri("/update")
+            .to_request();
+        test::call_service(&app, req).await;
+        {
+            let jobs = state.jobs.lock().unwrap();
+            assert!(matches!(jobs.get(1).unwrap().status, Status::Submitted));
+            assert_eq!(jobs.get(1).unwrap().attempt, 2);
+            assert_eq!(jobs.queued_len(), 1);
+        }
+
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(FetchRequest {
+                worker_id: "some_worker".to_string(),
+                free: Resources {
+                    threads: 1,
+                    memory: None,
+                    disk: None,
+                },
+                wait: None,
+                max_jobs: None,
+            })
+            .uri("/fetch")
+            .to_request();
+        let resp: FetchResponse = test::call_and_read_body_json(&app, req).await;
+        assert!(matches!(resp, FetchResponse::Jobs(jobs) if jobs[0].id == 1));
+
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(vec![failed()])
+            .uri("/update")
+            .to_request();
+        test::call_service(&app, req).await;
+        let jobs = state.jobs.lock().unwrap();
+        assert!(matches!(jobs.get(1).unwrap().status, Status::Failed));
+        assert_eq!(jobs.get(1).unwrap().attempt, 2);
+        assert_eq!(jobs.get(1).unwrap().history.len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_update_from_lost_worker() {
+        let mut state = State::new();
+        state.retry.max_lost_worker_retries = 1;
+        state.jobs.lock().unwrap().extend([
+            Job {
+                id: 1,
+                status: Status::Running("lost_worker".to_string()),
+                worker: Some("lost_worker".to_string()),
+                ..Default::default()
+            },
+            Job {
+                id: 2,
+                status: Status::Running("lost_worker".to_string()),
+                worker: Some("lost_worker".to_string()),
+                history: vec![Attempt {
+                    attempt: 1,
+                    status: Status::Running("other_worker".to_string()),
+                    worker: Some("other_worker".to_string()),
+                    started_at: None,
+                    finished_at: None,
+                    exit_code: None,
+                    signal: None,
+                    reason: None,
+                    lost_worker: true,
+                }],
+                ..Default::default()
+            },
+        ]);
+        state.reap(100, 60, None);
+        {
+            let jobs = state.jobs.lock().unwrap();
+            assert!(matches!(jobs.get(1).unwrap().status, Status::Submitted));
+            assert!(matches!(jobs.get(2).unwrap().status, Status::Failed));
+        }
+
+        let app = test::init_service(
+            App::new()
+                .app_data(String::from("secret"))
+                .app_data(web::Data::new(state))
+                .service(update)
+                .service(status),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(vec![Update {
+                worker: "lost_worker".to_string(),
+                job: 1,
+                status: Status::Completed,
+                exit_code: Some(0),
+                signal: None,
+                reason: None,
+            }])
+            .uri("/update")
+            .to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(vec![StatusRequest { id: 1 }])
+            .uri("/status")
+            .to_request();
+        let resp: Vec<Job> = test::call_and_read_body_json(&app, req).await;
+        assert!(matches!(resp[0].status, Status::Submitted));
+    }
+
+    #[actix_web::test]
+    async fn test_cancel() {
+        let state = web::Data::new(State::new());
+        let queued = Job {
+            id: 1,
+            cmd: String::from("sleep 100"),
+            status: Status::Submitted,
+            threads: 1,
+            ..Default::default()
+        };
+        state.jobs.lock().unwrap().insert(queued);
+        state.jobs.lock().unwrap().insert(Job {
+            id: 2,
+            cmd: String::from("sleep 100"),
+            status: Status::Running("some_worker".to_string()),
+            threads: 1,
+            worker: Some("some_worker".to_string()),
+            ..Default::default()
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(String::from("secret"))
+                .app_data(state.clone())
+                .service(cancel)
+                .service(heartbeat),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(vec![CancelRequest { id: 1 }, CancelRequest { id: 2 }])
+            .uri("/cancel")
+            .to_request();
+        let resp: Vec<Job> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.len(), 2);
+        assert!(resp.iter().all(|j| matches!(j.status, Status::Cancelled)));
+        assert_eq!(state.jobs.lock().unwrap().queued_len(), 0);
+
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(Heartbeat {
+                id: "some_worker".to_string(),
+            })
+            .uri("/heartbeat")
+            .to_request();
+        let resp: HeartbeatResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.cancel, vec![2]);
+    }
+
+    #[actix_web::test]
+    async fn test_job_array() {
+        let state = web::Data::new(State::new());
+        state.jobs.lock().unwrap().counter = 4;
+        let app = test::init_service(
+            App::new()
+                .app_data(String::from("secret"))
+                .app_data(state.clone())
+                .service(submit_array)
+                .service(get_array)
+                .service(cancel_array),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(JobArray {
+                job: Job {
+                    cmd: String::from("run --seed {}"),
+                    ..Default::default()
+                },
+                params: ArrayParams::Range {
+                    start: 1,
+                    end: 7,
+                    step: 3,
+                },
+            })
+            .uri("/arrays")
+            .to_request();
+        let resp: Vec<Job> = test::call_and_read_body_json(&app, req).await;
+        let ids: Vec<i32> = resp.iter().map(|j| j.id).collect();
+        assert_eq!(ids, vec![5, 6, 7]);
+        assert!(resp.iter().all(|j| j.array_id == Some(5)));
+        assert_eq!(resp[2].array_index, Some(7));
+        assert_eq!(resp[2].cmd, "run --seed 7");
+
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(JobArray {
+                job: Job {
+                    argv: vec![String::from("echo"), String::from("{}")],
+                    ..Default::default()
+                },
+                params: ArrayParams::Values(vec![String::from("a"), String::from("b")]),
+            })
+            .uri("/arrays")
+            .to_request();
+        let resp: Vec<Job> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp[1].argv, vec!["echo", "b"]);
+        assert_eq!(resp[1].array_id, Some(8));
+        assert_eq!(resp[1].array_index, Some(1));
+
+        {
+            let mut jobs = state.jobs.lock().unwrap();
+            jobs.dequeue(5);
+            let job = jobs.get(5).unwrap().clone();
+            jobs.replace(Job {
+                status: Status::Completed,
+                ..job
+            });
+        }
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .uri("/arrays/5/cancel")
+            .to_request();
+        let resp: Vec<Job> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.len(), 2);
+        assert_eq!(state.jobs.lock().unwrap().queued_len(), 2);
+
+        let req = test::TestRequest::get()
+            .append_header(("Authorization", "Bearer secret"))
+            .uri("/arrays/5")
+            .to_request();
+        let resp: ArrayStatus = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.tasks, 3);
+        assert_eq!(resp.completed, 1);
+        assert_eq!(resp.cancelled, 2);
+
+        let req = test::TestRequest::get()
+            .append_header(("Authorization", "Bearer secret"))
+            .uri("/arrays/6")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(JobArray {
+                job: Job {
+                    cmd: String::from("hi"),
+                    ..Default::default()
+                },
+                params: ArrayParams::Range {
+                    start: 0,
+                    end: 1_000_000,
+                    step: 1,
+                },
+            })
+            .uri("/arrays")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(JobArray {
+                job: Job {
+                    cmd: String::from("hi"),
+                    ..Default::default()
+                },
+                params: ArrayParams::Range {
+                    start: i64::MIN,
+                    end: i64::MAX,
+                    step: i64::MAX,
+                },
+            })
+            .uri("/arrays")
+            .to_request();
+        let resp: Vec<Job> = test::call_and_read_body_json(&app, req).await;
+        let indices: Vec<Option<i64>> = resp.iter().map(|j| j.array_index).collect();
+        assert_eq!(indices, vec![Some(i64::MIN), Some(-1), Some(i64::MAX - 1)]);
+    }
+
+    #[actix_web::test]
+    async fn test_submit_dependencies() {
+        let state = web::Data::new(State::new());
+        let app = test::init_service(
+            App::new()
+                .app_data(String::from("secret"))
+                .app_data(state.clone())
+                .service(submit)
+                .service(update),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(vec![
+                Job {
+                    id: 100,
+                    cmd: String::from("first"),
+                    ..Default::default()
+                },
+                Job {
+                    cmd: String::from("second"),
+                    after: vec![100],
+                    ..Default::default()
+                },
+            ])
+            .uri("/submit")
+            .to_request();
+        let resp: Vec<Job> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp[1].after, vec![resp[0].id]);
+        assert_eq!(state.jobs.lock().unwrap().queued_len(), 1);
+
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(vec![Job {
+                cmd: String::from("third"),
+                after: vec![42],
+                ..Default::default()
+            }])
+            .uri("/submit")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+
+        {
+            let mut jobs = state.jobs.lock().unwrap();
+            jobs.dequeue(1);
+            let job = jobs.get(1).unwrap().clone();
+            jobs.replace(Job {
+                status: Status::Running("some_worker".to_string()),
+                ..job
+            });
+        }
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(vec![Update {
+                worker: "some_worker".to_string(),
+                job: 1,
+                status: Status::Completed,
+                exit_code: Some(0),
+                signal: None,
+                reason: None,
+            }])
+            .uri("/update")
+            .to_request();
+        test::call_service(&app, req).await;
+        let jobs = state.jobs.lock().unwrap();
+        assert_eq!(jobs.select(&state.scheduler, |_| true, 0), Some(2));
+    }
+
+    #[actix_web::test]
+    async fn test_tokens() {
+        let state = web::Data::new(State::new());
+        state.workers.lock().unwrap().push(Worker {
+            id: "some_worker".to_string(),
+            last_heartbeat: None,
+            ..Default::default()
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(String::from("secret"))
+                .app_data(state.clone())
+                .service(create_token)
+                .service(list_tokens)
+                .service(revoke_token)
+                .service(submit)
+                .service(fetch),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(TokenRequest {
+                name: "alice".to_string(),
+                role: Role::Submitter,
+            })
+            .uri("/tokens")
+            .to_request();
+        let token: TokenResponse = test::call_and_read_body_json(&app, req).await;
+        let bearer = format!("Bearer {}", token.token);
+        assert_ne!(state.tokens.lock().unwrap()[0].hash, token.token);
+
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", bearer.as_str()))
+            .set_json(vec![Job {
+                cmd: String::from("hi"),
+                owner: String::from("bob"),
+                ..Default::default()
+            }])
+            .uri("/submit")
+            .to_request();
+        let resp: Vec<Job> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp[0].owner, "alice");
+
+        // submitters must not fetch jobs
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", bearer.as_str()))
+            .set_json(FetchRequest {
+                worker_id: "some_worker".to_string(),
+                free: Resources {
+                    threads: 1,
+                    memory: None,
+                    disk: None,
+                },
+                wait: None,
+                max_jobs: None,
+            })
+            .uri("/fetch")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::get()
+            .append_header(("Authorization", bearer.as_str()))
+            .uri("/tokens")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::delete()
+            .append_header(("Authorization", "Bearer secret"))
+            .uri("/tokens/alice")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", bearer.as_str()))
+            .set_json(vec![Job {
+                cmd: String::from("hi"),
+                ..Default::default()
+            }])
+            .uri("/submit")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn test_reap() {
+        let state = State::new();
+        state.workers.lock().unwrap().push(Worker {
+            id: "lost_worker".to_string(),
+            last_heartbeat: Some(0),
+            ..Default::default()
+        });
+        state.jobs.lock().unwrap().extend([
+            Job {
+                id: 1,
+                cmd: String::from("hi"),
+                status: Status::Running("lost_worker".to_string()),
+                worker: Some("lost_worker".to_string()),
+                ..Default::default()
+            },
+            Job {
+                id: 2,
+                cmd: String::from("hi"),
+                status: Status::Completed,
+                finished_at: Some(0),
+                ..Default::default()
+            },
+            Job {
+                id: 3,
+                cmd: String::from("hi"),
+                status: Status::Completed,
+                finished_at: Some(0),
+                ..Default::default()
+            },
+            Job {
+                id: 4,
+                cmd: String::from("hi"),
+                after: vec![1, 3],
+                ..Default::default()
+            },
+        ]);
+        state.reap(100, 60, Some(3600));
+        assert!(state.workers.lock().unwrap().is_empty());
+        {
+            let jobs = state.jobs.lock().unwrap();
+            assert!(matches!(jobs.get(1).unwrap().status, Status::Submitted));
+            assert!(jobs.get(1).unwrap().history[0].lost_worker);
+            assert_eq!(
+                jobs.get(1).unwrap().history[0].reason.as_deref(),
+                Some("worker lost_worker stopped sending heartbeats")
+            );
+            assert_eq!(jobs.len(), 4);
+            assert_eq!(jobs.queued_len(), 1);
+        }
+
+        // job 3 is kept for job 4, which still waits for job 1
+        state.reap(3600, 60, Some(3600));
+        {
+            let jobs = state.jobs.lock().unwrap();
+            let ids: Vec<i32> = jobs.iter().map(|j| j.id).collect();
+            assert_eq!(ids, vec![1, 3, 4]);
+        }
+        {
+            let mut jobs = state.jobs.lock().unwrap();
+            jobs.dequeue(1);
+            let job = jobs.get(1).unwrap().clone();
+            jobs.replace(Job {
+                status: Status::Completed,
+                finished_at: Some(3600),
+                ..job
+            });
+        }
+        state.reap(3600, 60, Some(3600));
+        let jobs = state.jobs.lock().unwrap();
+        assert!(matches!(jobs.get(4).unwrap().status, Status::Submitted));
+        assert_eq!(jobs.queued_len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_log() {
+        let app = test::init_service(
+            App::new()
+                .app_data(String::from("secret"))
+                .app_data(web::Data::new(State::new()))
+                .service(upload_log)
+                .service(job_log)
+                .service(view_log),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(Log {
+                worker: "some_worker".to_string(),
+                job: 3,
+                stdout: String::from("hello"),
+                stderr: String::from("world"),
+            })
+            .uri("/log")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let req = test::TestRequest::get()
+            .append_header(("Authorization", "Bearer secret"))
+            .uri("/log/3")
+            .to_request();
+        let resp: Log = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.stdout, "hello");
+        assert_eq!(resp.stderr, "world");
+
+        let req = test::TestRequest::get()
+            .append_header(("Authorization", "Bearer secret"))
+            .uri("/log/4")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+
+        // browsers ask for the token as password of basic authentication
+        let req = test::TestRequest::get().uri("/log/3/view").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::UNAUTHORIZED);
+        assert!(resp.headers().contains_key("WWW-Authenticate"));
+
+        let req = test::TestRequest::get()
+            .append_header(("Authorization", "Basic dXNlcjpzZWNyZXQ="))
+            .uri("/log/3/view")
+            .to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        assert!(String::from_utf8(body.to_vec()).unwrap().contains("hello"));
+    }
+
     #[actix_web::test]
     async fn test_submit() {
         let app = test::init_service(
@@ -404,16 +2354,189 @@ mod tests {
         )
         .await;
         let req = test::TestRequest::post()
-            .append_header(("cookie", "secret"))
+            .append_header(("Authorization", "Bearer secret"))
             .set_json(vec![Job {
                 id: 0,
                 cmd: String::from("hi"),
                 status: Status::Submitted,
                 threads: 1,
+                ..Default::default()
             }])
             .uri("/submit")
             .to_request();
         let resp = test::call_service(&app, req).await;
         assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let req = test::TestRequest::post()
+            .append_header(("Authorization", "Bearer secret"))
+            .set_json(vec![Job {
+                argv: vec![String::from("echo"), String::from("hi there")],
+                workdir: Some(String::from("/tmp")),
+                ..Default::default()
+            }])
+            .uri("/submit")
+            .to_request();
+        let resp: Vec<Job> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp[0].cmd, "echo hi there");
+        assert_eq!(resp[0].workdir.as_deref(), Some("/tmp"));
+
+        for job in [
+            Job::default(),
+            Job {
+                cmd: String::from("hi"),
+                memory: -1,
+                ..Default::default()
+            },
+            Job {
+                cmd: String::from("hi"),
+                workdir: Some(String::from("relative")),
+                ..Default::default()
+            },
+            Job {
+                cmd: String::from("hi"),
+                env: [(String::from("A=B"), String::from("C"))].into(),
+                ..Default::default()
+            },
+        ] {
+            let req = test::TestRequest::post()
+                .append_header(("Authorization", "Bearer secret"))
+                .set_json(vec![job])
+                .uri("/submit")
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+        }
+    }
+
+    /// Run with `cargo test --release -p zoidberg_server -- --ignored --nocapture`.
+    #[actix_web::test]
+    #[ignore]
+    async fn bench_queued_jobs() {
+        const QUEUED: i32 = 100_000;
+        const WAITING: i32 = 100_000;
+        const REQUESTS: i32 = 1_000;
+        let state = State::new();
+        state.workers.lock().unwrap().push(Worker {
+            id: "some_worker".to_string(),
+            ..Default::default()
+        });
+        state
+            .jobs
+            .lock()
+            .unwrap()
+            .extend((1..=QUEUED).map(|id| Job {
+                id,
+                cmd: String::from("hi"),
+                threads: 1,
+                priority: id % 10,
+                owner: format!("user{}", id % 20),
+                ..Default::default()
+            }));
+        // every queued job has a dependent that waits for it
+        state
+            .jobs
+            .lock()
+            .unwrap()
+            .extend((QUEUED + 1..=QUEUED + WAITING).map(|id| Job {
+                id,
+                cmd: String::from("hi"),
+                after: vec![id - QUEUED],
+                ..Default::default()
+            }));
+        let app = test::init_service(
+            App::new()
+                .app_data(String::from("secret"))
+                .app_data(web::Data::new(state))
+                .service(fetch)
+                .service(status)
+                .service(update)
+                .service(heartbeat),
+        )
+        .await;
+
+        let started = std::time::Instant::now();
+        let mut fetched = Vec::new();
+        for _ in 0..REQUESTS {
+            let req = test::TestRequest::post()
+                .append_header(("Authorization", "Bearer secret"))
+                .set_json(FetchRequest {
+                    worker_id: "some_worker".to_string(),
+                    free: Resources {
+                        threads: 1,
+                        memory: None,
+                        disk: None,
+                    },
+                    wait: None,
+                    max_jobs: None,
+                })
+                .uri("/fetch")
+                .to_request();
+            match test::call_and_read_body_json(&app, req).await {
+                FetchResponse::Jobs(jobs) => fetched.push(jobs[0].id),
+                _ => panic!("expected FetchResponse::Jobs"),
+            }
+        }
+        println!(
+            "fetch:  {:?} per request",
+            started.elapsed() / REQUESTS as u32
+        );
+
+        let started = std::time::Instant::now();
+        for i in 0..REQUESTS {
+            let ids: Vec<StatusRequest> = (0..100)
+                .map(|n| StatusRequest {
+                    id: (i * 100 + n) % QUEUED + 1,
+                })
+                .collect();
+            let req = test::TestRequest::post()
+                .append_header(("Authorization", "Bearer secret"))
+                .set_json(ids)
+                .uri("/status")
+                .to_request();
+            let resp: Vec<Job> = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(resp.len(), 100);
+        }
+        println!(
+            "status: {:?} per request of 100 jobs",
+            started.elapsed() / REQUESTS as u32
+        );
+
+        let started = std::time::Instant::now();
+        for id in fetched {
+            let req = test::TestRequest::post()
+                .append_header(("Authorization", "Bearer secret"))
+                .set_json(vec![Update {
+                    worker: "some_worker".to_string(),
+                    job: id,
+                    status: Status::Completed,
+                    exit_code: Some(0),
+                    signal: None,
+                    reason: None,
+                }])
+                .uri("/update")
+                .to_request();
+            test::call_service(&app, req).await;
+        }
+        println!(
+            "update: {:?} per request",
+            started.elapsed() / REQUESTS as u32
+        );
+
+        let started = std::time::Instant::now();
+        for _ in 0..REQUESTS {
+            let req = test::TestRequest::post()
+                .append_header(("Authorization", "Bearer secret"))
+                .set_json(Heartbeat {
+                    id: "some_worker".to_string(),
+                })
+                .uri("/heartbeat")
+                .to_request();
+            let resp: HeartbeatResponse = test::call_and_read_body_json(&app, req).await;
+            assert!(resp.cancel.is_empty());
+        }
+        println!(
+            "heartbeat: {:?} per request",
+            started.elapsed() / REQUESTS as u32
+        );
     }
 }
@@ -0,0 +1,100 @@
+use zoidberg_lib::types::{Attempt, Job, Status};
+
+/// Number of times a job is put back into the queue.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Retries after the job failed, unless the job sets `max_retries`.
+    pub max_retries: i32,
+    /// Retries after the worker running the job was lost.
+    pub max_lost_worker_retries: i32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 0,
+            max_lost_worker_retries: 3,
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn allows(&self, job: &Job, lost_worker: bool) -> bool {
+        let limit = if lost_worker {
+            self.max_lost_worker_retries
+        } else {
+            job.max_retries.unwrap_or(self.max_retries)
+        };
+        let used = job
+            .history
+            .iter()
+            .filter(|a| a.lost_worker == lost_worker)
+            .count();
+        (used as i32) < limit
+    }
+
+    /// Moves the current attempt of a failed job, or of a job whose worker
+    /// was lost, into its history and resets
+    /// the job to `Status::Submitted`, if the job has retries left.
+    ///
+    /// Returns whether the job should be queued again.
+    pub fn retry(&self, job: &mut Job, lost_worker: bool) -> bool {
+        if !self.allows(job, lost_worker) {
+            return false;
+        }
+        job.history.push(Attempt {
+            attempt: job.attempt,
+            status: job.status.clone(),
+            worker: job.worker.take(),
+            started_at: job.started_at.take(),
+            finished_at: job.finished_at.take(),
+            exit_code: job.exit_code.take(),
+            signal: job.signal.take(),
+            reason: job.reason.take(),
+            lost_worker,
+        });
+        job.attempt += 1;
+        job.status = Status::Submitted;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_limits() {
+        let policy = RetryPolicy {
+            max_retries: 1,
+            max_lost_worker_retries: 2,
+        };
+        let mut job = Job {
+            id: 1,
+            cmd: String::from("false"),
+            status: Status::Failed,
+            attempt: 1,
+            ..Default::default()
+        };
+        assert!(policy.retry(&mut job, false));
+        assert_eq!(job.attempt, 2);
+        assert!(matches!(job.status, Status::Submitted));
+
+        job.status = Status::Failed;
+        assert!(!policy.retry(&mut job, false));
+        assert!(policy.retry(&mut job, true));
+        job.status = Status::Failed;
+        assert!(policy.retry(&mut job, true));
+        job.status = Status::Failed;
+        assert!(!policy.retry(&mut job, true));
+        assert_eq!(job.history.len(), 3);
+        assert_eq!(job.attempt, 4);
+
+        let mut job = Job {
+            max_retries: Some(0),
+            status: Status::Failed,
+            ..Default::default()
+        };
+        assert!(!policy.retry(&mut job, false));
+    }
+}
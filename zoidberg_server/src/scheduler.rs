@@ -0,0 +1,200 @@
+use serde::Deserialize;
+use std::cmp::{Ordering, Reverse};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use zoidberg_lib::types::Job;
+
+/// Order in which queued jobs are handed out to workers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Policy {
+    /// Highest priority first, then jobs with more threads, then the oldest.
+    #[default]
+    Priority,
+    /// Oldest job first.
+    Fifo,
+    /// Jobs of the owner with the least recent usage first, then by priority.
+    FairShare,
+}
+
+/// Sort key of queued jobs, the smallest is handed out first.
+pub type Key = (Reverse<i32>, Reverse<i32>, i32);
+
+impl Policy {
+    /// Order of the jobs of a single owner, fair-share only decides
+    /// between owners.
+    pub fn key(&self, job: &Job) -> Key {
+        match self {
+            Policy::Priority | Policy::FairShare => {
+                (Reverse(job.priority), Reverse(job.threads), job.id)
+            }
+            Policy::Fifo => (Reverse(0), Reverse(0), job.id),
+        }
+    }
+}
+
+impl FromStr for Policy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "priority" => Ok(Policy::Priority),
+            "fifo" => Ok(Policy::Fifo),
+            "fair-share" => Ok(Policy::FairShare),
+            _ => Err(format!(
+                "unknown scheduler policy {}, expected priority, fifo or fair-share",
+                s
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for Policy {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Threads handed out per owner, decaying exponentially over time.
+struct Usage {
+    /// Seconds within which the usage halves.
+    half_life: f64,
+    owners: HashMap<String, (f64, i64)>,
+}
+
+impl Usage {
+    fn new(half_life: f64) -> Self {
+        Usage {
+            half_life,
+            owners: HashMap::new(),
+        }
+    }
+
+    fn get(&self, owner: &str, now: i64) -> f64 {
+        match self.owners.get(owner) {
+            Some((value, t)) => value * 0.5f64.powf((now - t) as f64 / self.half_life),
+            None => 0.0,
+        }
+    }
+
+    fn charge(&mut self, owner: &str, amount: f64, now: i64) {
+        let value = self.get(owner, now) + amount;
+        self.owners.insert(owner.to_string(), (value, now));
+    }
+}
+
+pub struct Scheduler {
+    pub policy: Policy,
+    usage: Mutex<Usage>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Scheduler::new(Policy::default(), 3600)
+    }
+}
+
+impl Scheduler {
+    pub fn new(policy: Policy, half_life: u64) -> Self {
+        Scheduler {
+            policy,
+            usage: Mutex::new(Usage::new(half_life as f64)),
+        }
+    }
+
+    /// Picks the job that is handed out next.
+    pub fn select<'a>(
+        &self,
+        candidates: impl Iterator<Item = &'a Job>,
+        now: i64,
+    ) -> Option<&'a Job> {
+        match self.policy {
+            Policy::Priority | Policy::Fifo => candidates.min_by_key(|j| self.policy.key(j)),
+            Policy::FairShare => {
+                let usage = self.usage.lock().unwrap();
+                candidates
+                    .map(|j| (usage.get(&j.owner, now), j))
+                    .min_by(|(ua, a), (ub, b)| {
+                        ua.partial_cmp(ub)
+                            .unwrap_or(Ordering::Equal)
+                            .then_with(|| self.policy.key(a).cmp(&self.policy.key(b)))
+                    })
+                    .map(|(_, j)| j)
+            }
+        }
+    }
+
+    /// Accounts a job that was handed out to its owner.
+    pub fn charge(&self, job: &Job, now: i64) {
+        self.usage
+            .lock()
+            .unwrap()
+            .charge(&job.owner, job.threads.max(1) as f64, now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jobs() -> Vec<Job> {
+        [(1, 0, 1, "alice"), (2, 5, 1, "alice"), (3, 0, 4, "bob")]
+            .iter()
+            .map(|(id, priority, threads, owner)| Job {
+                id: *id,
+                cmd: String::from("hi"),
+                priority: *priority,
+                threads: *threads,
+                owner: owner.to_string(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_priority() {
+        let jobs = jobs();
+        let s = Scheduler::new(Policy::Priority, 3600);
+        assert_eq!(s.select(jobs.iter(), 0).unwrap().id, 2);
+        assert_eq!(
+            s.select(jobs.iter().filter(|j| j.id != 2), 0).unwrap().id,
+            3
+        );
+    }
+
+    #[test]
+    fn test_fifo() {
+        let jobs = jobs();
+        let s = Scheduler::new(Policy::Fifo, 3600);
+        assert_eq!(s.select(jobs.iter(), 0).unwrap().id, 1);
+    }
+
+    #[test]
+    fn test_fair_share() {
+        let jobs = jobs();
+        let s = Scheduler::new(Policy::FairShare, 3600);
+        assert_eq!(s.select(jobs.iter(), 0).unwrap().id, 2);
+        s.charge(&jobs[1], 0);
+        assert_eq!(s.select(jobs.iter(), 0).unwrap().id, 3);
+        s.charge(&jobs[2], 0);
+        // bob used four threads, alice one
+        assert_eq!(s.select(jobs.iter(), 0).unwrap().id, 2);
+    }
+
+    #[test]
+    fn test_usage_decays() {
+        let mut usage = Usage::new(60.0);
+        usage.charge("alice", 4.0, 0);
+        assert_eq!(usage.get("alice", 60), 2.0);
+        assert_eq!(usage.get("bob", 0), 0.0);
+    }
+
+    #[test]
+    fn test_parse_policy() {
+        assert_eq!("fair-share".parse::<Policy>(), Ok(Policy::FairShare));
+        assert!("random".parse::<Policy>().is_err());
+    }
+}
@@ -0,0 +1,445 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use zoidberg_lib::types::{Job, Log, Worker};
+
+use crate::auth::Token;
+
+/// Everything that is needed to restore the server state after a restart.
+#[derive(Default)]
+pub struct Snapshot {
+    pub counter_jobs: i32,
+    pub jobs: Vec<Job>,
+    pub workers: Vec<Worker>,
+    /// IDs of the jobs that have a log, logs are read by `load_log`.
+    pub logs: Vec<i32>,
+    pub tokens: Vec<Token>,
+}
+
+/// Backend that persists jobs, their logs, workers, tokens and the job ID
+/// counter.
+pub trait Storage: Send + Sync {
+    fn load(&self) -> io::Result<Snapshot>;
+    fn save_counter(&self, counter: i32) -> io::Result<()>;
+    fn save_job(&self, job: &Job) -> io::Result<()>;
+    /// Forgets a job together with its log.
+    fn remove_job(&self, id: i32) -> io::Result<()>;
+    fn save_worker(&self, worker: &Worker) -> io::Result<()>;
+    fn remove_worker(&self, id: &str) -> io::Result<()>;
+    fn save_log(&self, log: &Log) -> io::Result<()>;
+    fn load_log(&self, job: i32) -> io::Result<Option<Log>>;
+    fn save_token(&self, token: &Token) -> io::Result<()>;
+    fn remove_token(&self, name: &str) -> io::Result<()>;
+    /// Shrinks what was written since the last compaction, if that is worth
+    /// it.
+    fn compact(&self) -> io::Result<()>;
+}
+
+/// Keeps nothing but the logs, which are not held by the server, the state
+/// is lost when the server stops.
+#[derive(Default)]
+pub struct Memory {
+    logs: Mutex<HashMap<i32, Log>>,
+}
+
+impl Storage for Memory {
+    fn load(&self) -> io::Result<Snapshot> {
+        Ok(Snapshot::default())
+    }
+
+    fn save_counter(&self, _counter: i32) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn save_job(&self, _job: &Job) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn remove_job(&self, id: i32) -> io::Result<()> {
+        self.logs.lock().unwrap().remove(&id);
+        Ok(())
+    }
+
+    fn save_worker(&self, _worker: &Worker) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn remove_worker(&self, _id: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn save_log(&self, log: &Log) -> io::Result<()> {
+        self.logs.lock().unwrap().insert(log.job, log.clone());
+        Ok(())
+    }
+
+    fn load_log(&self, job: i32) -> io::Result<Option<Log>> {
+        Ok(self.logs.lock().unwrap().get(&job).cloned())
+    }
+
+    fn save_token(&self, _token: &Token) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn remove_token(&self, _name: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn compact(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+enum Record {
+    Counter(i32),
+    Job(Box<Job>),
+    RemoveJob(i32),
+    Worker(Worker),
+    RemoveWorker(String),
+    Log(Log),
+    Token(Token),
+    RemoveToken(String),
+}
+
+/// Journals smaller than this are not compacted while the server runs.
+const MIN_COMPACT_SIZE: u64 = 1024 * 1024;
+
+/// Append-only journal with one JSON record per line.
+///
+/// Every change is appended to the file, later records of the same job or
+/// worker replace earlier ones. The journal is compacted when it is loaded
+/// and whenever it doubled in size since. Logs are kept in one file per job
+/// in the directory `<journal>.logs`, so that they do not bloat the journal.
+pub struct Journal {
+    path: PathBuf,
+    logs: PathBuf,
+    file: Mutex<File>,
+    /// Size of the journal after it was compacted the last time.
+    compacted: AtomicU64,
+}
+
+impl Journal {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let logs = path.with_extension("logs");
+        fs::create_dir_all(&logs)?;
+        Ok(Journal {
+            path: path.to_path_buf(),
+            logs,
+            file: Mutex::new(file),
+            compacted: AtomicU64::new(0),
+        })
+    }
+
+    fn log_path(&self, job: i32) -> PathBuf {
+        self.logs.join(format!("{}.json", job))
+    }
+
+    fn append(&self, record: &Record) -> io::Result<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes())?;
+        file.flush()
+    }
+
+    /// Reads the state from the journal, together with the log records of
+    /// older journals, which kept the logs in the journal itself.
+    fn replay(&self) -> io::Result<(Snapshot, Vec<Log>)> {
+        let mut counter_jobs = 0;
+        let mut jobs: HashMap<i32, Job> = HashMap::new();
+        let mut workers: HashMap<String, Worker> = HashMap::new();
+        let mut logs: HashMap<i32, Log> = HashMap::new();
+        let mut tokens: HashMap<String, Token> = HashMap::new();
+
+        let reader = BufReader::new(File::open(&self.path)?);
+        for (n, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(Record::Counter(c)) => counter_jobs = c,
+                Ok(Record::Job(j)) => {
+                    jobs.insert(j.id, *j);
+                }
+                Ok(Record::RemoveJob(id)) => {
+                    jobs.remove(&id);
+                    logs.remove(&id);
+                }
+                Ok(Record::Worker(w)) => {
+                    workers.insert(w.id.clone(), w);
+                }
+                Ok(Record::RemoveWorker(id)) => {
+                    workers.remove(&id);
+                }
+                Ok(Record::Log(l)) => {
+                    logs.insert(l.job, l);
+                }
+                Ok(Record::Token(t)) => {
+                    tokens.insert(t.name.clone(), t);
+                }
+                Ok(Record::RemoveToken(name)) => {
+                    tokens.remove(&name);
+                }
+                Err(e) => log::warn!(
+                    "Skipping unreadable record in line {} of {}: {}",
+                    n + 1,
+                    self.path.display(),
+                    e
+                ),
+            }
+        }
+        // only the names of the log files are read, logs are loaded when
+        // they are asked for
+        let mut ids: BTreeSet<i32> = logs.keys().copied().collect();
+        for entry in fs::read_dir(&self.logs)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|e| e != "json") {
+                continue;
+            }
+            match path.file_stem().and_then(|s| s.to_str()?.parse().ok()) {
+                Some(id) => {
+                    ids.insert(id);
+                }
+                None => log::warn!("Skipping log with unexpected name {}", path.display()),
+            }
+        }
+        ids.retain(|id| jobs.contains_key(id));
+
+        let mut jobs: Vec<Job> = jobs.into_values().collect();
+        jobs.sort_by_key(|j| j.id);
+        let counter_jobs = jobs.iter().map(|j| j.id).fold(counter_jobs, i32::max);
+        let snapshot = Snapshot {
+            counter_jobs,
+            jobs,
+            workers: workers.into_values().collect(),
+            logs: ids.into_iter().collect(),
+            tokens: tokens.into_values().collect(),
+        };
+        Ok((snapshot, logs.into_values().collect()))
+    }
+
+    /// Replaces the journal by the records of its current state, appends
+    /// wait until it is done.
+    fn rewrite(&self) -> io::Result<Snapshot> {
+        let mut file = self.file.lock().unwrap();
+        let (snapshot, legacy) = self.replay()?;
+        // log files replace the log records of older journals
+        for log in legacy.iter() {
+            if !self.log_path(log.job).exists() {
+                self.write_log(log)?;
+            }
+        }
+        let tmp = self.path.with_extension("tmp");
+        {
+            let mut out = File::create(&tmp)?;
+            let records = std::iter::once(Record::Counter(snapshot.counter_jobs))
+                .chain(
+                    snapshot
+                        .jobs
+                        .iter()
+                        .map(|j| Record::Job(Box::new(j.clone()))),
+                )
+                .chain(snapshot.workers.iter().cloned().map(Record::Worker))
+                .chain(snapshot.tokens.iter().cloned().map(Record::Token));
+            for record in records {
+                serde_json::to_writer(&mut out, &record)?;
+                out.write_all(b"\n")?;
+            }
+            out.sync_all()?;
+        }
+        fs::rename(&tmp, &self.path)?;
+        *file = OpenOptions::new().append(true).open(&self.path)?;
+        self.compacted
+            .store(file.metadata()?.len(), Ordering::Relaxed);
+        Ok(snapshot)
+    }
+
+    fn write_log(&self, log: &Log) -> io::Result<()> {
+        // a crash while writing leaves the previous log intact
+        let path = self.log_path(log.job);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(log)?)?;
+        fs::rename(&tmp, &path)
+    }
+}
+
+impl Storage for Journal {
+    fn load(&self) -> io::Result<Snapshot> {
+        self.rewrite()
+    }
+
+    fn save_counter(&self, counter: i32) -> io::Result<()> {
+        self.append(&Record::Counter(counter))
+    }
+
+    fn save_job(&self, job: &Job) -> io::Result<()> {
+        self.append(&Record::Job(Box::new(job.clone())))
+    }
+
+    fn remove_job(&self, id: i32) -> io::Result<()> {
+        self.append(&Record::RemoveJob(id))?;
+        match fs::remove_file(self.log_path(id)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn save_worker(&self, worker: &Worker) -> io::Result<()> {
+        self.append(&Record::Worker(worker.clone()))
+    }
+
+    fn remove_worker(&self, id: &str) -> io::Result<()> {
+        self.append(&Record::RemoveWorker(id.to_string()))
+    }
+
+    fn save_log(&self, log: &Log) -> io::Result<()> {
+        self.write_log(log)
+    }
+
+    fn load_log(&self, job: i32) -> io::Result<Option<Log>> {
+        match fs::read(self.log_path(job)) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn save_token(&self, token: &Token) -> io::Result<()> {
+        self.append(&Record::Token(token.clone()))
+    }
+
+    fn remove_token(&self, name: &str) -> io::Result<()> {
+        self.append(&Record::RemoveToken(name.to_string()))
+    }
+
+    fn compact(&self) -> io::Result<()> {
+        let size = self.file.lock().unwrap().metadata()?.len();
+        if size > MIN_COMPACT_SIZE.max(2 * self.compacted.load(Ordering::Relaxed)) {
+            log::info!("Compacting journal {}", self.path.display());
+            self.rewrite()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zoidberg_lib::types::Status;
+
+    #[test]
+    fn test_journal_restore() {
+        let path = std::env::temp_dir().join(format!("zoidberg-{}.journal", uuid::Uuid::new_v4()));
+        {
+            let journal = Journal::open(&path).unwrap();
+            let mut job = Job {
+                id: 1,
+                cmd: String::from("hi"),
+                status: Status::Submitted,
+                threads: 1,
+                ..Default::default()
+            };
+            journal.save_counter(1).unwrap();
+            journal.save_job(&job).unwrap();
+            job.status = Status::Completed;
+            journal.save_job(&job).unwrap();
+            journal
+                .save_job(&Job {
+                    id: 2,
+                    ..job.clone()
+                })
+                .unwrap();
+            journal.remove_job(2).unwrap();
+            journal
+                .save_worker(&Worker {
+                    id: "some_worker".to_string(),
+                    last_heartbeat: None,
+                    ..Default::default()
+                })
+                .unwrap();
+            journal
+                .save_worker(&Worker {
+                    id: "other_worker".to_string(),
+                    last_heartbeat: None,
+                    ..Default::default()
+                })
+                .unwrap();
+            journal.remove_worker("other_worker").unwrap();
+            journal
+                .save_log(&Log {
+                    worker: "some_worker".to_string(),
+                    job: 1,
+                    stdout: String::from("hello"),
+                    stderr: String::new(),
+                })
+                .unwrap();
+        }
+
+        let journal = Journal::open(&path).unwrap();
+        let snapshot = journal.load().unwrap();
+        assert_eq!(snapshot.counter_jobs, 1);
+        assert_eq!(snapshot.jobs.len(), 1);
+        assert!(matches!(snapshot.jobs[0].status, Status::Completed));
+        assert_eq!(snapshot.workers.len(), 1);
+        assert_eq!(snapshot.workers[0].id, "some_worker");
+        assert_eq!(snapshot.logs, vec![1]);
+        assert_eq!(journal.load_log(1).unwrap().unwrap().stdout, "hello");
+        assert!(journal.load_log(2).unwrap().is_none());
+
+        // loading compacted the journal, loading again yields the same state
+        let snapshot = journal.load().unwrap();
+        assert_eq!(snapshot.jobs.len(), 1);
+        fs::remove_file(&path).unwrap();
+        fs::remove_dir_all(path.with_extension("logs")).unwrap();
+    }
+
+    #[test]
+    fn test_journal_compact() {
+        let path = std::env::temp_dir().join(format!("zoidberg-{}.journal", uuid::Uuid::new_v4()));
+        let journal = Journal::open(&path).unwrap();
+        let log = |job| Log {
+            worker: "some_worker".to_string(),
+            job,
+            stdout: String::from("hello"),
+            stderr: String::new(),
+        };
+        // logs of older journals are moved to their own files
+        journal.append(&Record::Log(log(1))).unwrap();
+        journal.load().unwrap();
+        assert!(journal.log_path(1).exists());
+        assert!(!fs::read_to_string(&path).unwrap().contains("hello"));
+
+        journal.save_log(&log(2)).unwrap();
+        let mut job = Job {
+            id: 2,
+            cmd: "x".repeat(1000),
+            ..Default::default()
+        };
+        for attempt in 0..1100 {
+            job.attempt = attempt;
+            journal.save_job(&job).unwrap();
+        }
+        let size = fs::metadata(&path).unwrap().len();
+        assert!(size > MIN_COMPACT_SIZE);
+        journal.compact().unwrap();
+        assert!(fs::metadata(&path).unwrap().len() < size / 100);
+        // nothing to do right after a compaction
+        journal.compact().unwrap();
+
+        journal.remove_job(1).unwrap();
+        assert!(!journal.log_path(1).exists());
+        let snapshot = Journal::open(&path).unwrap().load().unwrap();
+        assert_eq!(snapshot.jobs.len(), 1);
+        assert_eq!(snapshot.jobs[0].attempt, 1099);
+        assert_eq!(snapshot.logs, vec![2]);
+        fs::remove_file(&path).unwrap();
+        fs::remove_dir_all(path.with_extension("logs")).unwrap();
+    }
+}
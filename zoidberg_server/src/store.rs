@@ -0,0 +1,399 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use zoidberg_lib::types::{Job, Status};
+
+use crate::scheduler::{Key, Policy, Scheduler};
+
+/// All jobs by ID, together with the queue of jobs that are ready to run
+/// and the jobs that wait for their dependencies.
+#[derive(Default)]
+pub struct Store {
+    policy: Policy,
+    jobs: BTreeMap<i32, Job>,
+    /// Queued jobs of every owner, in the order they are handed out.
+    queues: HashMap<String, BTreeSet<Key>>,
+    /// Owner and key of every queued job, to find it in `queues`.
+    queued: HashMap<i32, (String, Key)>,
+    /// Submitted jobs whose dependencies did not finish yet.
+    waiting: BTreeSet<i32>,
+    /// Tasks of every job array.
+    arrays: HashMap<i32, BTreeSet<i32>>,
+    /// Jobs that list a job in `after`, by the ID of that job.
+    dependents: HashMap<i32, BTreeSet<i32>>,
+    /// Waiting jobs whose dependencies have to be checked again.
+    pending: BTreeSet<i32>,
+    /// Jobs that are running or still being killed, by worker.
+    held: HashMap<String, BTreeSet<i32>>,
+    /// ID of the latest submitted job.
+    pub counter: i32,
+}
+
+impl Store {
+    /// Sorts the queue for another scheduler policy.
+    pub fn set_policy(&mut self, policy: Policy) {
+        self.policy = policy;
+        let queued: Vec<i32> = self.queued.keys().copied().collect();
+        self.queues.clear();
+        self.queued.clear();
+        for id in queued {
+            self.enqueue(id);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    pub fn get(&self, id: i32) -> Option<&Job> {
+        self.jobs.get(&id)
+    }
+
+    /// Puts back a changed job, its place in the queue stays as it was
+    /// when it was queued. The waiting dependents of a job that finished
+    /// are checked again by the next `take_pending`.
+    pub fn replace(&mut self, job: Job) {
+        let id = job.id;
+        let finished = match self.jobs.get(&id) {
+            Some(old) => !old.status.is_final() && job.status.is_final(),
+            None => return,
+        };
+        self.release(id);
+        if let Some(worker) = holder(&job) {
+            self.held.entry(worker.clone()).or_default().insert(id);
+        }
+        self.jobs.insert(id, job);
+        if finished {
+            let waiting = &self.waiting;
+            let dependents = self.dependents.get(&id).into_iter().flatten();
+            self.pending
+                .extend(dependents.filter(|child| waiting.contains(child)));
+        }
+    }
+
+    /// All jobs, ordered by ID.
+    pub fn iter(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.values()
+    }
+
+    /// Adds a job, submitted jobs are queued right away or wait for their
+    /// dependencies.
+    pub fn insert(&mut self, job: Job) {
+        let id = job.id;
+        let submitted = matches!(job.status, Status::Submitted);
+        let independent = job.after.is_empty();
+        self.remove(id);
+        if let Some(array) = job.array_id {
+            self.arrays.entry(array).or_default().insert(id);
+        }
+        for parent in job.after.iter() {
+            self.dependents.entry(*parent).or_default().insert(id);
+        }
+        if let Some(worker) = holder(&job) {
+            self.held.entry(worker.clone()).or_default().insert(id);
+        }
+        self.jobs.insert(id, job);
+        match (submitted, independent) {
+            (true, true) => self.enqueue(id),
+            (true, false) => {
+                self.waiting.insert(id);
+                self.pending.insert(id);
+            }
+            _ => {}
+        }
+    }
+
+    /// Puts a job into the queue, it is handed out by `select`.
+    pub fn enqueue(&mut self, id: i32) {
+        let job = match self.jobs.get(&id) {
+            Some(job) => job,
+            None => return,
+        };
+        let key = self.policy.key(job);
+        let owner = job.owner.clone();
+        self.waiting.remove(&id);
+        self.pending.remove(&id);
+        if let Some((owner, key)) = self.queued.insert(id, (owner.clone(), key)) {
+            self.queues.entry(owner).or_default().remove(&key);
+        }
+        self.queues.entry(owner).or_default().insert(key);
+    }
+
+    /// Takes a job out of the queue or from the waiting jobs, returns
+    /// whether it was in one of them.
+    pub fn dequeue(&mut self, id: i32) -> bool {
+        let waiting = self.waiting.remove(&id);
+        self.pending.remove(&id);
+        match self.queued.remove(&id) {
+            Some((owner, key)) => {
+                if let Some(queue) = self.queues.get_mut(&owner) {
+                    queue.remove(&key);
+                    if queue.is_empty() {
+                        self.queues.remove(&owner);
+                    }
+                }
+                true
+            }
+            None => waiting,
+        }
+    }
+
+    pub fn queued_len(&self) -> usize {
+        self.queued.len()
+    }
+
+    /// IDs of the jobs that wait for their dependencies.
+    #[cfg(test)]
+    pub fn waiting(&self) -> Vec<i32> {
+        self.waiting.iter().copied().collect()
+    }
+
+    /// Takes the waiting job with the lowest ID among the ones that were
+    /// submitted or whose dependencies finished since they were checked.
+    pub fn take_pending(&mut self) -> Option<i32> {
+        self.pending.pop_first()
+    }
+
+    /// Jobs that are running on a worker or that the worker still has to
+    /// kill, ordered by ID.
+    pub fn held(&self, worker: &str) -> impl Iterator<Item = &Job> {
+        self.held
+            .get(worker)
+            .into_iter()
+            .flatten()
+            .map(|id| &self.jobs[id])
+    }
+
+    /// Workers that hold jobs.
+    pub fn holders(&self) -> impl Iterator<Item = &String> {
+        self.held.keys()
+    }
+
+    /// Picks the queued job that is handed out next among the ones that
+    /// `fits` accepts, without taking it out of the queue.
+    pub fn select(
+        &self,
+        scheduler: &Scheduler,
+        fits: impl Fn(&Job) -> bool,
+        now: i64,
+    ) -> Option<i32> {
+        // the first fitting job of every owner is the only candidate of
+        // that owner, jobs that do not fit are skipped
+        let candidates: Vec<&Job> = self
+            .queues
+            .values()
+            .filter_map(|queue| {
+                queue
+                    .iter()
+                    .map(|(_, _, id)| &self.jobs[id])
+                    .find(|job| fits(job))
+            })
+            .collect();
+        scheduler
+            .select(candidates.into_iter(), now)
+            .map(|job| job.id)
+    }
+
+    /// Tasks of a job array, ordered by ID.
+    pub fn array(&self, id: i32) -> impl Iterator<Item = &Job> {
+        self.arrays
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .map(|id| &self.jobs[id])
+    }
+
+    /// Keeps only the jobs for which `keep` returns true. Jobs that
+    /// unfinished jobs depend on are always kept, `keep` is not called
+    /// for them.
+    pub fn retain(&mut self, mut keep: impl FnMut(&Job) -> bool) {
+        let removed: Vec<i32> = self
+            .jobs
+            .values()
+            .filter(|j| !self.needed(j.id) && !keep(j))
+            .map(|j| j.id)
+            .collect();
+        for id in removed {
+            self.remove(id);
+        }
+    }
+
+    /// Whether an unfinished job lists the job in `after`.
+    fn needed(&self, id: i32) -> bool {
+        self.dependents
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .any(|child| !self.jobs[child].status.is_final())
+    }
+
+    /// Removes a job and forgets it in every index.
+    fn remove(&mut self, id: i32) {
+        self.dequeue(id);
+        self.release(id);
+        let job = match self.jobs.remove(&id) {
+            Some(job) => job,
+            None => return,
+        };
+        if let Some(array) = job.array_id {
+            let tasks = self.arrays.entry(array).or_default();
+            tasks.remove(&id);
+            if tasks.is_empty() {
+                self.arrays.remove(&array);
+            }
+        }
+        for parent in job.after.iter() {
+            let dependents = self.dependents.entry(*parent).or_default();
+            dependents.remove(&id);
+            if dependents.is_empty() {
+                self.dependents.remove(parent);
+            }
+        }
+    }
+
+    /// Forgets the worker that holds a job.
+    fn release(&mut self, id: i32) {
+        let worker = match self.jobs.get(&id).and_then(holder) {
+            Some(worker) => worker,
+            None => return,
+        };
+        if let Some(jobs) = self.held.get_mut(worker) {
+            jobs.remove(&id);
+            if jobs.is_empty() {
+                self.held.remove(worker);
+            }
+        }
+    }
+}
+
+/// Worker that runs a job or still has to kill it.
+fn holder(job: &Job) -> Option<&String> {
+    match &job.status {
+        Status::Running(worker) => Some(worker),
+        Status::Cancelled if job.finished_at.is_none() => job.worker.as_ref(),
+        _ => None,
+    }
+}
+
+impl Extend<Job> for Store {
+    fn extend<T: IntoIterator<Item = Job>>(&mut self, jobs: T) {
+        for job in jobs {
+            self.insert(job);
+        }
+    }
+}
+
+impl FromIterator<Job> for Store {
+    fn from_iter<T: IntoIterator<Item = Job>>(jobs: T) -> Self {
+        let mut store = Store::default();
+        store.extend(jobs);
+        store
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(id: i32, priority: i32, threads: i32, owner: &str) -> Job {
+        Job {
+            id,
+            cmd: String::from("hi"),
+            priority,
+            threads,
+            owner: owner.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_queue() {
+        let mut store = Store::default();
+        store.insert(job(1, 0, 1, "alice"));
+        store.insert(job(2, 5, 1, "alice"));
+        store.insert(job(3, 0, 4, "bob"));
+        store.insert(Job {
+            after: vec![3],
+            ..job(4, 9, 1, "bob")
+        });
+        assert_eq!(store.queued_len(), 3);
+        assert_eq!(store.waiting(), vec![4]);
+
+        let scheduler = Scheduler::new(Policy::Priority, 3600);
+        assert_eq!(store.select(&scheduler, |_| true, 0), Some(2));
+        assert_eq!(store.select(&scheduler, |j| j.threads < 4, 0), Some(2));
+        assert_eq!(store.select(&scheduler, |j| j.id != 2, 0), Some(3));
+        assert!(store.dequeue(2));
+        assert!(!store.dequeue(2));
+        assert_eq!(store.select(&scheduler, |j| j.threads < 4, 0), Some(1));
+
+        store.set_policy(Policy::Fifo);
+        let scheduler = Scheduler::new(Policy::Fifo, 3600);
+        assert_eq!(store.select(&scheduler, |_| true, 0), Some(1));
+
+        store.insert(Job {
+            array_id: Some(5),
+            ..job(5, 0, 1, "alice")
+        });
+        store.insert(Job {
+            array_id: Some(5),
+            ..job(6, 0, 1, "bob")
+        });
+        let tasks: Vec<i32> = store.array(5).map(|j| j.id).collect();
+        assert_eq!(tasks, vec![5, 6]);
+
+        store.retain(|j| j.owner != "alice");
+        assert_eq!(store.len(), 3);
+        let tasks: Vec<i32> = store.array(5).map(|j| j.id).collect();
+        assert_eq!(tasks, vec![6]);
+        store.retain(|j| j.id != 6);
+        assert_eq!(store.array(5).count(), 0);
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.select(&scheduler, |_| true, 0), Some(3));
+        assert!(store.select(&scheduler, |j| j.threads < 4, 0).is_none());
+    }
+
+    #[test]
+    fn test_changed_job_stays_in_place() {
+        let mut store = Store::default();
+        store.insert(job(1, 0, 1, "alice"));
+        store.replace(job(1, 10, 1, "bob"));
+        assert!(store.dequeue(1));
+        assert_eq!(store.queued_len(), 0);
+    }
+
+    #[test]
+    fn test_held() {
+        let mut store = Store::default();
+        store.insert(Job {
+            status: Status::Running(String::from("w1")),
+            ..job(1, 0, 1, "alice")
+        });
+        store.insert(Job {
+            status: Status::Cancelled,
+            worker: Some(String::from("w1")),
+            ..job(2, 0, 1, "alice")
+        });
+        store.insert(Job {
+            status: Status::Running(String::from("w2")),
+            ..job(3, 0, 1, "alice")
+        });
+        let held: Vec<i32> = store.held("w1").map(|j| j.id).collect();
+        assert_eq!(held, vec![1, 2]);
+
+        // the cancelled job is killed, the other one finished
+        store.replace(Job {
+            status: Status::Cancelled,
+            worker: Some(String::from("w1")),
+            finished_at: Some(0),
+            ..job(2, 0, 1, "alice")
+        });
+        store.replace(Job {
+            status: Status::Completed,
+            ..job(3, 0, 1, "alice")
+        });
+        let held: Vec<i32> = store.held("w1").map(|j| j.id).collect();
+        assert_eq!(held, vec![1]);
+        let holders: Vec<&String> = store.holders().collect();
+        assert_eq!(holders, vec!["w1"]);
+    }
+}
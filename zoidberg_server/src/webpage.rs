@@ -1,16 +1,31 @@
 use chrono::Utc;
-use zoidberg_lib::types::{Job, Worker};
+use std::collections::HashSet;
+use zoidberg_lib::types::{Job, Log, Worker};
 
 // TODO: write nicer frontend
-pub fn render(jobs: &[Job], workers: &[Worker]) -> String {
+pub fn render(jobs: &[Job], workers: &[Worker], logs: &HashSet<i32>) -> String {
+    let now = Utc::now().timestamp();
     let jobs_html: String = String::from("<table class=\"table is-hoverable\">")
-        + "<thead><tr><th>ID</th><th style=\"width: 150px;\">command</th><th>status</th></tr></thead><tbody>"
+        + "<thead><tr><th>ID</th><th style=\"width: 150px;\">command</th><th>status</th><th>runtime</th><th>log</th></tr></thead><tbody>"
         + &jobs
             .iter()
             .map(|j| {
+                let log = if logs.contains(&j.id) {
+                    format!("<a href=\"/log/{}/view\">log</a>", j.id)
+                } else {
+                    String::from("")
+                };
+                let status = match &j.reason {
+                    Some(reason) => format!("{} ({})", j.status, reason),
+                    None => format!("{}", j.status),
+                };
+                let runtime = match j.runtime(now) {
+                    Some(t) => format!("{}", t),
+                    None => String::from(""),
+                };
                 format!(
-                    "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
-                    j.id, j.cmd, j.status
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    j.id, j.cmd, status, runtime, log
                 )
             })
             .collect::<Vec<String>>()
@@ -18,7 +33,7 @@ pub fn render(jobs: &[Job], workers: &[Worker]) -> String {
         + "</tbody></table>";
 
     let workers_html: String = String::from("<table class=\"table is-hoverable\">")
-        + "<thead><tr><th>ID</th><th>last heartbeat</th></tr></thead><tbody>"
+        + "<thead><tr><th>ID</th><th>last heartbeat</th><th>state</th></tr></thead><tbody>"
         + &workers
             .iter()
             .map(|w| {
@@ -27,7 +42,11 @@ pub fn render(jobs: &[Job], workers: &[Worker]) -> String {
                 } else {
                     String::from("")
                 };
-                format!("<tr><td>{}</td><td>{}</td></tr>", w.id, ts)
+                let state = if w.draining { "draining" } else { "active" };
+                format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    w.id, ts, state
+                )
             })
             .collect::<Vec<String>>()
             .join("\n")
@@ -95,3 +114,46 @@ pub fn render(jobs: &[Job], workers: &[Worker]) -> String {
     );
     page
 }
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn render_log(log: &Log) -> String {
+    format!(
+        r#"
+<!DOCTYPE html>
+<html>
+  <head>
+    <meta charset="utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>Zoidberg - log of job {id}</title>
+    <link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/bulma@0.9.4/css/bulma.min.css">
+  </head>
+  <body>
+  <section class="section">
+    <div class="container">
+      <h1 class="title">Log of job {id}</h1>
+      <p class="subtitle">worker {worker}</p>
+      <div class="block">
+        <h2 class="subtitle">stdout</h2>
+        <pre>{stdout}</pre>
+      </div>
+      <div class="block">
+        <h2 class="subtitle">stderr</h2>
+        <pre>{stderr}</pre>
+      </div>
+    </div>
+  </section>
+  </body>
+</html>
+"#,
+        id = log.job,
+        worker = escape(&log.worker),
+        stdout = escape(&log.stdout),
+        stderr = escape(&log.stderr),
+    )
+}